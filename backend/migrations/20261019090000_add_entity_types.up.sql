@@ -0,0 +1,84 @@
+-- Registry of entity types, each declaring a JSON Schema for the attributes of its entities
+CREATE TABLE entity_types (
+    name VARCHAR(50) PRIMARY KEY,
+    description TEXT,
+    attributes_schema JSONB NOT NULL DEFAULT '{"type": "object"}'
+);
+
+INSERT INTO entity_types (name, description, attributes_schema) VALUES
+(
+    'service',
+    'An application or API serving requests',
+    '{
+        "type": "object",
+        "properties": {
+            "repository": {"type": "string"},
+            "language": {"type": "string"},
+            "endpoint": {"type": "string"}
+        }
+    }'
+),
+(
+    'database',
+    'A persistent data store',
+    '{
+        "type": "object",
+        "properties": {
+            "engine": {"type": "string"},
+            "version": {"type": "string"},
+            "replicas": {"type": "integer", "minimum": 0}
+        }
+    }'
+),
+(
+    'vm',
+    'A virtual machine',
+    '{
+        "type": "object",
+        "properties": {
+            "os": {"type": "string"},
+            "cpus": {"type": "integer", "minimum": 1},
+            "memory_gb": {"type": "number", "exclusiveMinimum": 0}
+        }
+    }'
+),
+(
+    'host',
+    'A physical or virtual host running workloads',
+    '{
+        "type": "object",
+        "properties": {
+            "os": {"type": "string"},
+            "cpus": {"type": "integer", "minimum": 1},
+            "memory_gb": {"type": "number", "exclusiveMinimum": 0}
+        }
+    }'
+),
+(
+    'cluster',
+    'A group of hosts scheduled together, e.g. Kubernetes',
+    '{
+        "type": "object",
+        "properties": {
+            "provider": {"type": "string"},
+            "region": {"type": "string"},
+            "nodes": {"type": "integer", "minimum": 0}
+        }
+    }'
+),
+(
+    'network',
+    'A network segment connecting entities',
+    '{
+        "type": "object",
+        "properties": {
+            "cidr": {"type": "string"},
+            "vlan": {"type": "integer", "minimum": 0, "maximum": 4095}
+        }
+    }'
+);
+
+-- Entities created before the registry may use types that are not registered yet
+INSERT INTO entity_types (name)
+SELECT DISTINCT type FROM entities
+ON CONFLICT (name) DO NOTHING;
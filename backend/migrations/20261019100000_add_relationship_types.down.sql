@@ -0,0 +1,2 @@
+DROP TABLE relationship_type_rules;
+DROP TABLE relationship_types;
@@ -0,0 +1,44 @@
+-- Registry of relationship types with optional rules restricting the entity types on each end
+CREATE TABLE relationship_types (
+    name VARCHAR(50) PRIMARY KEY,
+    description TEXT,
+    attributes_schema JSONB NOT NULL DEFAULT '{"type": "object"}'
+);
+
+-- A relationship type without any rules may join entities of any type
+CREATE TABLE relationship_type_rules (
+    relationship_type VARCHAR(50) NOT NULL REFERENCES relationship_types(name) ON DELETE CASCADE,
+    from_type VARCHAR(50) NOT NULL REFERENCES entity_types(name) ON DELETE CASCADE,
+    to_type VARCHAR(50) NOT NULL REFERENCES entity_types(name) ON DELETE CASCADE,
+    PRIMARY KEY (relationship_type, from_type, to_type)
+);
+
+INSERT INTO relationship_types (name, description) VALUES
+    ('depends_on', 'The source needs the target to function'),
+    ('hosted_on', 'The source runs on the target machine'),
+    ('runs_in', 'The source is scheduled inside the target cluster'),
+    ('connects_via', 'The source reaches its dependencies through the target network'),
+    ('reads_from', 'The source service reads data from the target database'),
+    ('writes_to', 'The source service writes data to the target database');
+
+INSERT INTO relationship_type_rules (relationship_type, from_type, to_type) VALUES
+    ('hosted_on', 'service', 'host'),
+    ('hosted_on', 'service', 'vm'),
+    ('hosted_on', 'database', 'host'),
+    ('hosted_on', 'database', 'vm'),
+    ('hosted_on', 'vm', 'host'),
+    ('runs_in', 'service', 'cluster'),
+    ('runs_in', 'database', 'cluster'),
+    ('runs_in', 'vm', 'cluster'),
+    ('connects_via', 'service', 'network'),
+    ('connects_via', 'database', 'network'),
+    ('connects_via', 'vm', 'network'),
+    ('connects_via', 'host', 'network'),
+    ('connects_via', 'cluster', 'network'),
+    ('reads_from', 'service', 'database'),
+    ('writes_to', 'service', 'database');
+
+-- Relationships created before the registry may use types that are not registered yet
+INSERT INTO relationship_types (name)
+SELECT DISTINCT relationship_type FROM relationships
+ON CONFLICT (name) DO NOTHING;
@@ -0,0 +1,5 @@
+DROP INDEX entities_search_text_trgm_idx;
+DROP INDEX entities_search_document_idx;
+DROP FUNCTION entity_search_document;
+DROP FUNCTION entity_search_text;
+DROP FUNCTION entity_attribute_text;
@@ -0,0 +1,28 @@
+-- Full-text and trigram search over entity names, types and string attribute values
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- All string values found anywhere inside the attributes document, space separated
+CREATE FUNCTION entity_attribute_text(attributes JSONB) RETURNS TEXT
+LANGUAGE SQL IMMUTABLE PARALLEL SAFE AS $$
+    SELECT COALESCE(string_agg(value #>> '{}', ' '), '')
+    FROM jsonb_path_query(attributes, 'strict $.** ? (@.type() == "string")') AS value
+$$;
+
+CREATE FUNCTION entity_search_text(name VARCHAR, type VARCHAR, attributes JSONB) RETURNS TEXT
+LANGUAGE SQL IMMUTABLE PARALLEL SAFE AS $$
+    SELECT name || ' ' || type || ' ' || entity_attribute_text(attributes)
+$$;
+
+-- Matches on the name rank above the type which rank above attribute values
+CREATE FUNCTION entity_search_document(name VARCHAR, type VARCHAR, attributes JSONB) RETURNS TSVECTOR
+LANGUAGE SQL IMMUTABLE PARALLEL SAFE AS $$
+    SELECT setweight(to_tsvector('simple', name), 'A')
+        || setweight(to_tsvector('simple', type), 'B')
+        || setweight(to_tsvector('simple', entity_attribute_text(attributes)), 'C')
+$$;
+
+CREATE INDEX entities_search_document_idx ON entities
+    USING GIN (entity_search_document(name, type, attributes));
+
+CREATE INDEX entities_search_text_trgm_idx ON entities
+    USING GIN (entity_search_text(name, type, attributes) gin_trgm_ops);
@@ -0,0 +1,4 @@
+DROP INDEX relationships_labels_idx;
+DROP INDEX entities_labels_idx;
+ALTER TABLE relationships DROP COLUMN labels;
+ALTER TABLE entities DROP COLUMN labels;
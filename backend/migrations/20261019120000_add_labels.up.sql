@@ -0,0 +1,7 @@
+-- Kubernetes style labels, a flat map of string keys to string values
+ALTER TABLE entities ADD COLUMN labels JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE relationships ADD COLUMN labels JSONB NOT NULL DEFAULT '{}';
+
+-- Supports the containment (@>) and key existence (?) operators used by label selectors
+CREATE INDEX entities_labels_idx ON entities USING GIN (labels);
+CREATE INDEX relationships_labels_idx ON relationships USING GIN (labels);
@@ -0,0 +1,5 @@
+DROP INDEX entities_owner_team_id_idx;
+ALTER TABLE entities DROP COLUMN owner_team_id;
+DROP TABLE escalation_contacts;
+DROP TABLE team_members;
+DROP TABLE teams;
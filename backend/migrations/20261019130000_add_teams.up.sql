@@ -0,0 +1,28 @@
+-- Teams of users that own entities
+CREATE TABLE teams (
+    id BIGSERIAL PRIMARY KEY,
+    name VARCHAR(50) NOT NULL UNIQUE,
+    description TEXT
+);
+
+CREATE TABLE team_members (
+    team_id BIGINT NOT NULL REFERENCES teams(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role VARCHAR(50) NOT NULL DEFAULT 'member',
+    PRIMARY KEY (team_id, user_id)
+);
+
+-- Ordered escalation chain for a team, level 1 is paged first
+CREATE TABLE escalation_contacts (
+    team_id BIGINT NOT NULL REFERENCES teams(id) ON DELETE CASCADE,
+    level INTEGER NOT NULL CHECK (level > 0),
+    position INTEGER NOT NULL,
+    user_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    channel VARCHAR(50) NOT NULL,
+    address VARCHAR(255) NOT NULL,
+    PRIMARY KEY (team_id, position)
+);
+
+-- Entities without an owner are kept when their team is deleted
+ALTER TABLE entities ADD COLUMN owner_team_id BIGINT REFERENCES teams(id) ON DELETE SET NULL;
+CREATE INDEX entities_owner_team_id_idx ON entities (owner_team_id);
@@ -0,0 +1 @@
+DROP TABLE sli_measurements;
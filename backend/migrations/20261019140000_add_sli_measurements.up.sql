@@ -0,0 +1,13 @@
+-- Time series of measured SLIs, compared against the declared values on entities
+CREATE TABLE sli_measurements (
+    id BIGSERIAL PRIMARY KEY,
+    entity_id BIGINT NOT NULL REFERENCES entities(id) ON DELETE CASCADE,
+    measured_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    availability DOUBLE PRECISION,
+    p95_millis INTEGER,
+    p99_millis INTEGER,
+    throughput_rps INTEGER,
+    source VARCHAR(50) NOT NULL DEFAULT 'manual'
+);
+
+CREATE INDEX sli_measurements_entity_time_idx ON sli_measurements (entity_id, measured_at DESC);
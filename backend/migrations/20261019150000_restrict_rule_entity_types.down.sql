@@ -0,0 +1,7 @@
+ALTER TABLE relationship_type_rules
+    DROP CONSTRAINT relationship_type_rules_from_type_fkey,
+    DROP CONSTRAINT relationship_type_rules_to_type_fkey,
+    ADD CONSTRAINT relationship_type_rules_from_type_fkey
+        FOREIGN KEY (from_type) REFERENCES entity_types(name) ON DELETE CASCADE,
+    ADD CONSTRAINT relationship_type_rules_to_type_fkey
+        FOREIGN KEY (to_type) REFERENCES entity_types(name) ON DELETE CASCADE;
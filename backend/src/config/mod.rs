@@ -57,6 +57,8 @@
 //!
 //! This example demonstrates how to load a YAML configuration string and merge it
 //! with secrets stored in a specified directory.
+pub mod check;
+
 use std::path::Path;
 
 use figment::{
@@ -69,10 +71,12 @@ use serde::Deserialize;
 use url::Url;
 
 use crate::{
-    persistence::PersistenceConfig, tokio_tools::ThreadRuntime, webserver::WebServiceConfig,
+    graphql::GraphqlConfig, hams::Checks, logging::LoggingConfig, persistence::PersistenceConfig,
+    reload::ReloadConfig, risk::RiskConfig, scraper::ScraperConfig, telemetry::TelemetryConfig,
+    tokio_tools::ThreadRuntime, webserver::WebServiceConfig,
 };
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct UrlWithUsernamePassword {
     pub url: Url,
     pub username: Option<String>,
@@ -97,9 +101,30 @@ impl From<UrlWithUsernamePassword> for Url {
 pub struct MyConfig {
     /// Config of my web service
     pub hams: HamsConfig,
+    /// URLs probed before serving and once shutdown starts, none when not configured
+    #[serde(default)]
+    pub checks: Checks,
     pub runtime: ThreadRuntime,
     pub webservice: WebServiceConfig,
     pub persistence: PersistenceConfig,
+    /// Prometheus scraping of observed SLIs, disabled when not configured
+    #[serde(default)]
+    pub scraper: ScraperConfig,
+    /// Log filter, reloadable while running
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    /// Watching the config for hot reload
+    #[serde(default)]
+    pub reload: ReloadConfig,
+    /// Trace export, disabled when not configured
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+    /// Limits of the GraphQL endpoint
+    #[serde(default)]
+    pub graphql: GraphqlConfig,
+    /// Weights of the risk score, reloadable while running
+    #[serde(default)]
+    pub risk: RiskConfig,
 }
 
 impl MyConfig {
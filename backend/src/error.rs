@@ -17,6 +17,8 @@ pub enum MyError {
     Message(&'static str),
     #[error("Service Cancelled")]
     Cancelled,
+    #[error("Validation error `{0}`")]
+    Validation(String),
 
     #[error("HaMs error `{0}`")]
     HamsError(#[from] HamsError),
@@ -32,6 +34,9 @@ pub enum MyError {
     #[error("Json Rejection `{0}`")]
     JsonRejection(#[from] JsonRejection),
 
+    #[error("Reqwest error `{0}`")]
+    ReqwestError(#[from] reqwest::Error),
+
     #[error("Sqlx error `{0}`")]
     SqlxError(#[from] sqlx::Error),
     #[error("SQLX Migrate error `{0}`")]
@@ -0,0 +1,112 @@
+//! Headers forwarded from inbound requests to responses and outbound calls
+//!
+//! `webservice.forwarding_headers` lists header names to forward, a trailing `*` matching any
+//! header with that prefix (e.g. `x-b3-*`). The matching headers of each request are kept for
+//! the task handling it, echoed on its response, and added by [`Forward::forward_headers`] to
+//! `reqwest` calls made while handling it. Outside a request, such as the background scraper
+//! or startup preflights, there is nothing to forward.
+//! The trace context of the current span is added too, see `telemetry`.
+
+use std::future::Future;
+
+use axum::http::HeaderMap;
+
+use crate::telemetry;
+
+tokio::task_local! {
+    static FORWARDED: HeaderMap;
+}
+
+fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name
+            .get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(prefix)),
+        None => name.eq_ignore_ascii_case(pattern),
+    }
+}
+
+/// The inbound headers matching any of `patterns`
+pub fn capture(patterns: &[String], headers: &HeaderMap) -> HeaderMap {
+    let mut forwarded = HeaderMap::new();
+    for (name, value) in headers {
+        if patterns
+            .iter()
+            .any(|pattern| matches(pattern, name.as_str()))
+        {
+            forwarded.append(name, value.clone());
+        }
+    }
+    forwarded
+}
+
+/// Add forwarded headers to a response, leaving any the handler set itself
+pub fn echo(forwarded: &HeaderMap, response: &mut HeaderMap) {
+    for name in forwarded.keys() {
+        if !response.contains_key(name) {
+            for value in forwarded.get_all(name) {
+                response.append(name, value.clone());
+            }
+        }
+    }
+}
+
+/// Run `f` with `forwarded` as the headers of the current request
+pub async fn scope<F: Future>(forwarded: HeaderMap, f: F) -> F::Output {
+    FORWARDED.scope(forwarded, f).await
+}
+
+/// Headers forwarded from the request being handled, empty outside a request
+pub fn current() -> HeaderMap {
+    FORWARDED.try_with(Clone::clone).unwrap_or_default()
+}
+
+pub trait Forward {
+    /// Attach the headers forwarded from the request being handled and the trace context
+    fn forward_headers(self) -> Self;
+}
+
+impl Forward for reqwest::RequestBuilder {
+    fn forward_headers(self) -> Self {
+        let mut headers = current();
+        telemetry::inject(&mut headers);
+        self.headers(headers)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn capture_echo_and_forward() {
+        let patterns = vec!["x-request-id".to_string(), "x-b3-*".to_string()];
+        let mut inbound = HeaderMap::new();
+        inbound.insert("x-request-id", HeaderValue::from_static("abc"));
+        inbound.insert("x-b3-traceid", HeaderValue::from_static("123"));
+        inbound.insert("authorization", HeaderValue::from_static("secret"));
+
+        let forwarded = capture(&patterns, &inbound);
+        assert_eq!(forwarded.len(), 2);
+        assert!(!forwarded.contains_key("authorization"));
+
+        let mut response = HeaderMap::new();
+        response.insert("x-request-id", HeaderValue::from_static("mine"));
+        echo(&forwarded, &mut response);
+        assert_eq!(response["x-request-id"], "mine");
+        assert_eq!(response["x-b3-traceid"], "123");
+
+        assert!(current().is_empty());
+        let request = scope(forwarded, async {
+            reqwest::Client::new()
+                .get("http://localhost/metrics")
+                .forward_headers()
+                .build()
+                .unwrap()
+        })
+        .await;
+        assert_eq!(request.headers()["x-b3-traceid"], "123");
+    }
+}
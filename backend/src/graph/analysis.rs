@@ -0,0 +1,191 @@
+//! Structural analyses over `n` nodes and `(from, to)` edges
+//!
+//! Like `layout` these work on plain node positions so they can be tested without a database.
+
+/// Strongly connected components by Tarjan's algorithm, iterative to cope with long chains
+///
+/// Components are returned in reverse topological order: a component only depends on
+/// components listed before it.
+pub fn strongly_connected_components(n: usize, edges: &[(usize, usize)]) -> Vec<Vec<usize>> {
+    let mut adjacency = vec![vec![]; n];
+    for &(from, to) in edges {
+        adjacency[from].push(to);
+    }
+
+    let mut index = vec![usize::MAX; n];
+    let mut low = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut stack = vec![];
+    let mut components = vec![];
+    let mut next = 0;
+
+    for root in 0..n {
+        if index[root] != usize::MAX {
+            continue;
+        }
+
+        // (node, position in its adjacency list)
+        let mut work = vec![(root, 0)];
+        index[root] = next;
+        low[root] = next;
+        next += 1;
+        stack.push(root);
+        on_stack[root] = true;
+
+        while let Some(&mut (node, ref mut child)) = work.last_mut() {
+            if let Some(&to) = adjacency[node].get(*child) {
+                *child += 1;
+                if index[to] == usize::MAX {
+                    index[to] = next;
+                    low[to] = next;
+                    next += 1;
+                    stack.push(to);
+                    on_stack[to] = true;
+                    work.push((to, 0));
+                } else if on_stack[to] {
+                    low[node] = low[node].min(index[to]);
+                }
+                continue;
+            }
+
+            work.pop();
+            if let Some(&(parent, _)) = work.last() {
+                low[parent] = low[parent].min(low[node]);
+            }
+
+            if low[node] == index[node] {
+                let mut component = vec![];
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                component.sort_unstable();
+                components.push(component);
+            }
+        }
+    }
+
+    components
+}
+
+/// Components that contain a cycle: more than one node, or a node depending on itself
+pub fn cyclic_components(n: usize, edges: &[(usize, usize)]) -> Vec<Vec<usize>> {
+    let mut self_loop = vec![false; n];
+    for &(from, to) in edges {
+        if from == to {
+            self_loop[from] = true;
+        }
+    }
+
+    strongly_connected_components(n, edges)
+        .into_iter()
+        .filter(|component| component.len() > 1 || self_loop[component[0]])
+        .collect()
+}
+
+/// Nodes whose removal disconnects the graph, ignoring edge direction
+///
+/// Found by Tarjan's low-link over a depth-first search, iterative like
+/// `strongly_connected_components`.
+pub fn articulation_points(n: usize, edges: &[(usize, usize)]) -> Vec<usize> {
+    // (neighbour, edge position) so that only the edge to the parent is skipped, not parallels
+    let mut adjacency = vec![vec![]; n];
+    for (edge, &(from, to)) in edges.iter().enumerate() {
+        if from != to {
+            adjacency[from].push((to, edge));
+            adjacency[to].push((from, edge));
+        }
+    }
+
+    let mut index = vec![usize::MAX; n];
+    let mut low = vec![0; n];
+    let mut articulation = vec![false; n];
+    let mut next = 0;
+
+    for root in 0..n {
+        if index[root] != usize::MAX {
+            continue;
+        }
+        index[root] = next;
+        low[root] = next;
+        next += 1;
+        let mut root_children = 0;
+
+        // (node, edge it was reached by, position in its adjacency list)
+        let mut work = vec![(root, usize::MAX, 0)];
+        while let Some(&mut (node, via, ref mut child)) = work.last_mut() {
+            if let Some(&(to, edge)) = adjacency[node].get(*child) {
+                *child += 1;
+                if edge == via {
+                    continue;
+                }
+                if index[to] == usize::MAX {
+                    index[to] = next;
+                    low[to] = next;
+                    next += 1;
+                    if node == root {
+                        root_children += 1;
+                    }
+                    work.push((to, edge, 0));
+                } else {
+                    low[node] = low[node].min(index[to]);
+                }
+                continue;
+            }
+
+            work.pop();
+            if let Some(&(parent, _, _)) = work.last() {
+                low[parent] = low[parent].min(low[node]);
+                if parent != root && low[node] >= index[parent] {
+                    articulation[parent] = true;
+                }
+            }
+        }
+        articulation[root] = root_children > 1;
+    }
+
+    (0..n).filter(|&node| articulation[node]).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn find_cyclic_components() {
+        // 0 -> 1 -> 2 -> 0 form a cycle, 3 depends on the cycle, 4 depends on itself
+        let edges = [(0, 1), (1, 2), (2, 0), (3, 0), (4, 4), (5, 3)];
+        let components = strongly_connected_components(6, &edges);
+        assert_eq!(components.len(), 4);
+        assert!(components.contains(&vec![0, 1, 2]));
+
+        let cyclic = cyclic_components(6, &edges);
+        assert_eq!(cyclic, vec![vec![0, 1, 2], vec![4]]);
+
+        assert!(cyclic_components(3, &[(0, 1), (1, 2), (0, 2)]).is_empty());
+    }
+
+    #[test]
+    fn find_articulation_points() {
+        // 0 - 1 - 2 chain, 2 joins the 2 - 3 - 4 triangle, 5 hangs off 4 by two parallel edges
+        let edges = [(0, 1), (1, 2), (2, 3), (3, 4), (4, 2), (5, 4), (5, 4)];
+        assert_eq!(articulation_points(6, &edges), vec![1, 2, 4]);
+
+        // Direction is ignored: 1 and 2 both depend on 0 and 3 depends on both
+        assert!(articulation_points(4, &[(1, 0), (2, 0), (3, 1), (3, 2)]).is_empty());
+    }
+
+    #[test]
+    fn long_chains_do_not_overflow() {
+        let n = 100_000;
+        let mut edges: Vec<(usize, usize)> = (0..n - 1).map(|i| (i, i + 1)).collect();
+        edges.push((n - 1, 0));
+        assert_eq!(
+            cyclic_components(n, &edges),
+            vec![(0..n).collect::<Vec<_>>()]
+        );
+    }
+}
@@ -0,0 +1,185 @@
+//! How failures cascade from dependencies to their consumers
+//!
+//! Follows the availability rule of the data model: dependencies of different types are in
+//! series, dependencies of the same type are parallel and back each other up. A consumer fails
+//! once every dependency of any one type has failed. Nodes carry their type as `kinds`, any
+//! number identifying it.
+
+use std::collections::{HashMap, HashSet};
+
+/// Consumers of each node and their dependencies per kind, built once to cascade many
+/// failures over the same graph
+#[derive(Debug, Clone)]
+pub struct Dependencies {
+    /// Consumers of each node, with the slot counting that consumer's dependencies of its kind
+    consumers: Vec<Vec<(usize, usize)>>,
+    /// Dependencies of each consumer and dependency kind, indexed by slot
+    slots: Vec<usize>,
+}
+
+/// Buffers reused by [`Dependencies::cascade_with`] so repeated cascades do not allocate
+#[derive(Debug)]
+pub struct Scratch {
+    /// Dependencies still up in each slot
+    up: Vec<usize>,
+    down: Vec<bool>,
+    /// Nodes down after the last cascade, in the order they failed
+    fallen: Vec<usize>,
+    /// Slots the last cascade counted down
+    touched: Vec<usize>,
+}
+
+impl Dependencies {
+    pub fn new(n: usize, edges: &[(usize, usize)], kinds: &[usize]) -> Self {
+        // Parallel relationships to the same dependency give no redundancy
+        let edges: HashSet<(usize, usize)> = edges
+            .iter()
+            .copied()
+            .filter(|(from, to)| from != to)
+            .collect();
+
+        let mut consumers = vec![vec![]; n];
+        let mut slot_of: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut slots = vec![];
+        for &(from, to) in &edges {
+            let slot = *slot_of.entry((from, kinds[to])).or_insert_with(|| {
+                slots.push(0);
+                slots.len() - 1
+            });
+            slots[slot] += 1;
+            consumers[to].push((from, slot));
+        }
+
+        Dependencies { consumers, slots }
+    }
+
+    pub fn scratch(&self) -> Scratch {
+        Scratch {
+            up: self.slots.clone(),
+            down: vec![false; self.consumers.len()],
+            fallen: vec![],
+            touched: vec![],
+        }
+    }
+
+    /// Which nodes are down once `failed` have failed, including `failed` themselves
+    pub fn cascade(&self, failed: &[usize]) -> Vec<bool> {
+        let mut scratch = self.scratch();
+        self.cascade_with(failed, &mut scratch);
+        scratch.down
+    }
+
+    /// The nodes down once `failed` have failed, including `failed` themselves, reusing the
+    /// buffers of `scratch` and only resetting what the previous cascade changed
+    pub fn cascade_with<'s>(&self, failed: &[usize], scratch: &'s mut Scratch) -> &'s [usize] {
+        for &slot in &scratch.touched {
+            scratch.up[slot] = self.slots[slot];
+        }
+        for &node in &scratch.fallen {
+            scratch.down[node] = false;
+        }
+        scratch.touched.clear();
+        scratch.fallen.clear();
+
+        for &node in failed {
+            if !scratch.down[node] {
+                scratch.down[node] = true;
+                scratch.fallen.push(node);
+            }
+        }
+
+        // `fallen` doubles as the queue of nodes whose consumers are still to be visited
+        let mut next = 0;
+        while let Some(&node) = scratch.fallen.get(next) {
+            next += 1;
+            for &(consumer, slot) in &self.consumers[node] {
+                if scratch.down[consumer] {
+                    continue;
+                }
+                scratch.up[slot] -= 1;
+                scratch.touched.push(slot);
+                if scratch.up[slot] == 0 {
+                    scratch.down[consumer] = true;
+                    scratch.fallen.push(consumer);
+                }
+            }
+        }
+
+        &scratch.fallen
+    }
+}
+
+/// For each node, the consumers that have no other dependency of its kind
+pub fn sole_dependency_of(n: usize, edges: &[(usize, usize)], kinds: &[usize]) -> Vec<Vec<usize>> {
+    let edges: HashSet<(usize, usize)> = edges
+        .iter()
+        .copied()
+        .filter(|(from, to)| from != to)
+        .collect();
+
+    let mut per_kind: HashMap<(usize, usize), usize> = HashMap::new();
+    for &(from, to) in &edges {
+        *per_kind.entry((from, kinds[to])).or_default() += 1;
+    }
+
+    let mut sole = vec![vec![]; n];
+    for &(from, to) in &edges {
+        if per_kind[&(from, kinds[to])] == 1 {
+            sole[to].push(from);
+        }
+    }
+    for consumers in &mut sole {
+        consumers.sort_unstable();
+    }
+    sole
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // 0 depends on 1 and 2 of kind 0 (parallel) and 3 of kind 1, 1 and 2 both run on 4,
+    // 5 depends on 0
+    const EDGES: [(usize, usize); 6] = [(0, 1), (0, 2), (0, 3), (1, 4), (2, 4), (5, 0)];
+    const KINDS: [usize; 6] = [0, 0, 0, 1, 2, 0];
+
+    #[test]
+    fn parallel_dependencies_back_each_other_up() {
+        let dependencies = Dependencies::new(6, &EDGES, &KINDS);
+        let down = |failed: &[usize]| -> Vec<usize> {
+            let down = dependencies.cascade(failed);
+            (0..6).filter(|&node| down[node]).collect()
+        };
+
+        assert_eq!(down(&[1]), vec![1]);
+        assert_eq!(down(&[1, 2]), vec![0, 1, 2, 5]);
+        assert_eq!(down(&[3]), vec![0, 3, 5]);
+        assert_eq!(down(&[4]), vec![0, 1, 2, 4, 5]);
+    }
+
+    #[test]
+    fn scratch_is_reset_between_cascades() {
+        let dependencies = Dependencies::new(6, &EDGES, &KINDS);
+        let mut scratch = dependencies.scratch();
+        let mut down = |failed: &[usize]| -> Vec<usize> {
+            let mut down = dependencies.cascade_with(failed, &mut scratch).to_vec();
+            down.sort_unstable();
+            down
+        };
+
+        assert_eq!(down(&[4]), vec![0, 1, 2, 4, 5]);
+        assert_eq!(down(&[1]), vec![1]);
+        assert_eq!(down(&[2]), vec![2]);
+        assert_eq!(down(&[1, 2]), vec![0, 1, 2, 5]);
+        assert_eq!(down(&[3]), vec![0, 3, 5]);
+    }
+
+    #[test]
+    fn sole_dependencies() {
+        let sole = sole_dependency_of(6, &EDGES, &KINDS);
+        assert!(sole[1].is_empty());
+        assert_eq!(sole[3], vec![0]);
+        assert_eq!(sole[4], vec![1, 2]);
+        assert_eq!(sole[0], vec![5]);
+    }
+}
@@ -0,0 +1,395 @@
+//! Automatic graph layout
+//!
+//! Two algorithms are provided, both working on node positions `0..n` and `(from, to)` edges:
+//!
+//! - [`layered`]: a Sugiyama style layout for dependency DAGs. Cycles are broken by reversing
+//!   back edges, nodes are layered by longest path (consumers above their dependencies), long
+//!   edges are split with dummy nodes and crossings are reduced with barycenter sweeps.
+//! - [`force`]: a Fruchterman-Reingold force-directed layout, started from a circle so that
+//!   the result is deterministic.
+//!
+//! Both return coordinates translated so that the smallest `x` and `y` are zero.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::MyError;
+
+/// Most barycenter sweeps a request may ask for
+pub const MAX_SWEEPS: usize = 50;
+/// Most simulation steps a request may ask for
+pub const MAX_ITERATIONS: usize = 2000;
+/// Largest node or layer spacing a request may ask for
+pub const MAX_SPACING: f64 = 10_000.0;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LayoutAlgorithm {
+    #[default]
+    Layered,
+    Force,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LayoutOptions {
+    /// Horizontal distance between neighbouring nodes (and ideal edge length for `force`),
+    /// greater than 0 and at most `MAX_SPACING`
+    pub node_spacing: f64,
+    /// Vertical distance between layers for `layered`, greater than 0 and at most `MAX_SPACING`
+    pub layer_spacing: f64,
+    /// Number of down/up barycenter sweeps for `layered`, at most `MAX_SWEEPS`
+    pub sweeps: usize,
+    /// Number of simulation steps for `force`, at most `MAX_ITERATIONS`
+    pub iterations: usize,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        Self {
+            node_spacing: 150.0,
+            layer_spacing: 120.0,
+            sweeps: 8,
+            iterations: 300,
+        }
+    }
+}
+
+impl LayoutOptions {
+    /// Reject options that would keep a worker busy for too long or give unusable coordinates
+    pub fn validate(&self) -> Result<(), MyError> {
+        for (name, spacing) in [
+            ("node_spacing", self.node_spacing),
+            ("layer_spacing", self.layer_spacing),
+        ] {
+            if !(spacing > 0.0 && spacing <= MAX_SPACING) {
+                return Err(MyError::Validation(format!(
+                    "{name} must be greater than 0 and at most {MAX_SPACING}"
+                )));
+            }
+        }
+        if self.sweeps > MAX_SWEEPS {
+            return Err(MyError::Validation(format!(
+                "sweeps must be at most {MAX_SWEEPS}"
+            )));
+        }
+        if self.iterations > MAX_ITERATIONS {
+            return Err(MyError::Validation(format!(
+                "iterations must be at most {MAX_ITERATIONS}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+pub fn layout(
+    algorithm: LayoutAlgorithm,
+    n: usize,
+    edges: &[(usize, usize)],
+    options: &LayoutOptions,
+) -> Vec<(f64, f64)> {
+    match algorithm {
+        LayoutAlgorithm::Layered => layered(n, edges, options),
+        LayoutAlgorithm::Force => force(n, edges, options),
+    }
+}
+
+/// Remove self loops and duplicate edges
+fn simple_edges(edges: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut simple: Vec<(usize, usize)> = edges.iter().copied().filter(|(a, b)| a != b).collect();
+    simple.sort_unstable();
+    simple.dedup();
+    simple
+}
+
+/// Reverse the back edges found by a depth first search so the result is acyclic
+fn break_cycles(n: usize, edges: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut adjacency = vec![vec![]; n];
+    for &(from, to) in edges {
+        adjacency[from].push(to);
+    }
+
+    // 0 = unvisited, 1 = on the stack, 2 = finished
+    let mut state = vec![0u8; n];
+    let mut back_edges = std::collections::HashSet::new();
+
+    for root in 0..n {
+        if state[root] != 0 {
+            continue;
+        }
+        let mut stack = vec![(root, 0usize)];
+        state[root] = 1;
+        while let Some((node, next)) = stack.pop() {
+            if next < adjacency[node].len() {
+                stack.push((node, next + 1));
+                let child = adjacency[node][next];
+                match state[child] {
+                    0 => {
+                        state[child] = 1;
+                        stack.push((child, 0));
+                    }
+                    1 => {
+                        back_edges.insert((node, child));
+                    }
+                    _ => {}
+                }
+            } else {
+                state[node] = 2;
+            }
+        }
+    }
+
+    let mut acyclic: Vec<(usize, usize)> = edges
+        .iter()
+        .map(|&(from, to)| {
+            if back_edges.contains(&(from, to)) {
+                (to, from)
+            } else {
+                (from, to)
+            }
+        })
+        .collect();
+    acyclic.sort_unstable();
+    acyclic.dedup();
+    acyclic
+}
+
+/// Longest path layering, nodes without incoming edges sit on layer 0
+fn assign_layers(n: usize, edges: &[(usize, usize)]) -> Vec<usize> {
+    let mut in_degree = vec![0usize; n];
+    let mut adjacency = vec![vec![]; n];
+    for &(from, to) in edges {
+        adjacency[from].push(to);
+        in_degree[to] += 1;
+    }
+
+    let mut layer = vec![0usize; n];
+    let mut ready: Vec<usize> = (0..n).filter(|&v| in_degree[v] == 0).rev().collect();
+    while let Some(node) = ready.pop() {
+        for &child in &adjacency[node] {
+            layer[child] = layer[child].max(layer[node] + 1);
+            in_degree[child] -= 1;
+            if in_degree[child] == 0 {
+                ready.push(child);
+            }
+        }
+    }
+    layer
+}
+
+/// Reorder `layers[target]` by the mean position of each node's neighbours in the fixed layer
+fn barycenter_sort(
+    layers: &mut [Vec<usize>],
+    position: &mut [usize],
+    neighbours: &[Vec<usize>],
+    target: usize,
+) {
+    let mut keyed: Vec<(f64, usize)> = layers[target]
+        .iter()
+        .map(|&node| {
+            let adjacent = &neighbours[node];
+            let key = if adjacent.is_empty() {
+                position[node] as f64
+            } else {
+                adjacent.iter().map(|&a| position[a] as f64).sum::<f64>() / adjacent.len() as f64
+            };
+            (key, node)
+        })
+        .collect();
+    keyed.sort_by(|a, b| a.0.total_cmp(&b.0).then(position[a.1].cmp(&position[b.1])));
+
+    layers[target] = keyed.into_iter().map(|(_, node)| node).collect();
+    for (idx, &node) in layers[target].iter().enumerate() {
+        position[node] = idx;
+    }
+}
+
+/// Layered (Sugiyama) layout, see module documentation
+pub fn layered(n: usize, edges: &[(usize, usize)], options: &LayoutOptions) -> Vec<(f64, f64)> {
+    if n == 0 {
+        return vec![];
+    }
+
+    let edges = break_cycles(n, &simple_edges(edges));
+    let mut layer = assign_layers(n, &edges);
+
+    // Split edges spanning several layers with dummy nodes so every edge joins adjacent layers
+    let mut up: Vec<Vec<usize>> = vec![vec![]; n];
+    let mut down: Vec<Vec<usize>> = vec![vec![]; n];
+    for &(from, to) in &edges {
+        let mut previous = from;
+        for dummy_layer in layer[from] + 1..layer[to] {
+            let dummy = layer.len();
+            layer.push(dummy_layer);
+            up.push(vec![previous]);
+            down.push(vec![]);
+            down[previous].push(dummy);
+            previous = dummy;
+        }
+        down[previous].push(to);
+        up[to].push(previous);
+    }
+
+    let depth = layer.iter().max().copied().unwrap_or(0) + 1;
+    let mut layers: Vec<Vec<usize>> = vec![vec![]; depth];
+    for (node, &l) in layer.iter().enumerate() {
+        layers[l].push(node);
+    }
+    let mut position = vec![0usize; layer.len()];
+    for nodes in &layers {
+        for (idx, &node) in nodes.iter().enumerate() {
+            position[node] = idx;
+        }
+    }
+
+    for _ in 0..options.sweeps {
+        for target in 1..depth {
+            barycenter_sort(&mut layers, &mut position, &up, target);
+        }
+        for target in (0..depth.saturating_sub(1)).rev() {
+            barycenter_sort(&mut layers, &mut position, &down, target);
+        }
+    }
+
+    let width = layers.iter().map(Vec::len).max().unwrap_or(1) as f64;
+    (0..n)
+        .map(|node| {
+            let row = layers[layer[node]].len() as f64;
+            let x = (position[node] as f64 + (width - row) / 2.0) * options.node_spacing;
+            let y = layer[node] as f64 * options.layer_spacing;
+            (x, y)
+        })
+        .collect()
+}
+
+/// Force-directed (Fruchterman-Reingold) layout, see module documentation
+pub fn force(n: usize, edges: &[(usize, usize)], options: &LayoutOptions) -> Vec<(f64, f64)> {
+    if n == 0 {
+        return vec![];
+    }
+
+    let edges = simple_edges(edges);
+    let k = options.node_spacing;
+    let radius = k * n as f64 / std::f64::consts::TAU;
+    let mut points: Vec<(f64, f64)> = (0..n)
+        .map(|i| {
+            let angle = std::f64::consts::TAU * i as f64 / n as f64;
+            (radius * angle.cos(), radius * angle.sin())
+        })
+        .collect();
+
+    let initial_temperature = k * (n as f64).sqrt();
+    for step in 0..options.iterations {
+        let temperature = initial_temperature * (1.0 - step as f64 / options.iterations as f64);
+        let mut displacement = vec![(0.0f64, 0.0f64); n];
+
+        for a in 0..n {
+            for b in a + 1..n {
+                let (dx, dy) = (points[a].0 - points[b].0, points[a].1 - points[b].1);
+                let distance = (dx * dx + dy * dy).sqrt().max(0.01);
+                let repulsion = k * k / distance;
+                let (fx, fy) = (dx / distance * repulsion, dy / distance * repulsion);
+                displacement[a].0 += fx;
+                displacement[a].1 += fy;
+                displacement[b].0 -= fx;
+                displacement[b].1 -= fy;
+            }
+        }
+
+        for &(a, b) in &edges {
+            let (dx, dy) = (points[a].0 - points[b].0, points[a].1 - points[b].1);
+            let distance = (dx * dx + dy * dy).sqrt().max(0.01);
+            let attraction = distance * distance / k;
+            let (fx, fy) = (dx / distance * attraction, dy / distance * attraction);
+            displacement[a].0 -= fx;
+            displacement[a].1 -= fy;
+            displacement[b].0 += fx;
+            displacement[b].1 += fy;
+        }
+
+        for (point, (dx, dy)) in points.iter_mut().zip(displacement) {
+            let length = (dx * dx + dy * dy).sqrt();
+            if length > 0.0 {
+                let limited = length.min(temperature);
+                point.0 += dx / length * limited;
+                point.1 += dy / length * limited;
+            }
+        }
+    }
+
+    let min_x = points.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+    let min_y = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+    points
+        .into_iter()
+        .map(|(x, y)| (x - min_x, y - min_y))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn layered_places_dependencies_below_consumers() {
+        let options = LayoutOptions::default();
+        // 0 -> 1 -> 2 and a shortcut 0 -> 2 plus a cycle back 2 -> 0
+        let points = layered(3, &[(0, 1), (1, 2), (0, 2), (2, 0)], &options);
+
+        assert_eq!(points.len(), 3);
+        assert!(points[0].1 < points[1].1);
+        assert!(points[1].1 < points[2].1);
+        assert!(points.iter().all(|p| p.0 >= 0.0 && p.1 >= 0.0));
+    }
+
+    #[test]
+    fn layered_uncrosses_edges() {
+        let options = LayoutOptions::default();
+        // Consumers 0,1 on top with dependencies 3,2 respectively in their initial order
+        let points = layered(4, &[(0, 3), (1, 2)], &options);
+
+        assert!(points[3].0 < points[2].0);
+    }
+
+    #[test]
+    fn options_are_bounded() {
+        assert!(LayoutOptions::default().validate().is_ok());
+        let options = LayoutOptions {
+            iterations: 1_000_000_000,
+            ..LayoutOptions::default()
+        };
+        assert!(matches!(options.validate(), Err(MyError::Validation(_))));
+        let options = LayoutOptions {
+            sweeps: MAX_SWEEPS + 1,
+            ..LayoutOptions::default()
+        };
+        assert!(matches!(options.validate(), Err(MyError::Validation(_))));
+        for spacing in [0.0, -1.0, f64::NAN, f64::INFINITY, MAX_SPACING * 2.0] {
+            let options = LayoutOptions {
+                node_spacing: spacing,
+                ..LayoutOptions::default()
+            };
+            assert!(matches!(options.validate(), Err(MyError::Validation(_))));
+            let options = LayoutOptions {
+                layer_spacing: spacing,
+                ..LayoutOptions::default()
+            };
+            assert!(matches!(options.validate(), Err(MyError::Validation(_))));
+        }
+    }
+
+    #[test]
+    fn force_is_deterministic_and_separates_nodes() {
+        let options = LayoutOptions::default();
+        let edges = [(0, 1), (1, 2), (2, 3), (3, 0)];
+        let first = force(4, &edges, &options);
+        let second = force(4, &edges, &options);
+
+        assert_eq!(first, second);
+        for a in 0..4 {
+            for b in a + 1..4 {
+                let distance =
+                    ((first[a].0 - first[b].0).powi(2) + (first[a].1 - first[b].1).powi(2)).sqrt();
+                assert!(distance > options.node_spacing / 4.0);
+            }
+        }
+    }
+}
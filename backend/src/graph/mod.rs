@@ -0,0 +1,227 @@
+//! In-memory view of the entity graph
+//!
+//! Loads `entities` and `relationships` from Postgres into adjacency lists so that
+//! whole-graph analyses (such as layout) can walk the graph without a query per hop.
+//! Edges point from the consumer (`from_id`) to its dependency (`to_id`).
+
+use std::collections::HashMap;
+
+use sqlx::PgPool;
+
+use crate::{
+    error::MyError,
+    webserver::{DbBigSerial, entities::Entity, relationships::Relationship},
+};
+
+pub mod analysis;
+pub mod failure;
+pub mod layout;
+pub mod paths;
+pub mod simulation;
+
+#[derive(Debug)]
+pub struct Graph {
+    pub entities: Vec<Entity>,
+    pub relationships: Vec<Relationship>,
+    index: HashMap<DbBigSerial, usize>,
+    /// Relationship indices keyed by the position of their `from_id` entity
+    outgoing: Vec<Vec<usize>>,
+    /// Relationship indices keyed by the position of their `to_id` entity
+    incoming: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    /// Build a graph, dropping any relationship whose ends are not both in `entities`
+    pub fn new(entities: Vec<Entity>, relationships: Vec<Relationship>) -> Graph {
+        let index: HashMap<DbBigSerial, usize> = entities
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, entity)| entity.id.map(|id| (id, idx)))
+            .collect();
+
+        let relationships: Vec<Relationship> = relationships
+            .into_iter()
+            .filter(|r| index.contains_key(&r.from_id) && index.contains_key(&r.to_id))
+            .collect();
+
+        let mut outgoing = vec![vec![]; entities.len()];
+        let mut incoming = vec![vec![]; entities.len()];
+        for (edge, relationship) in relationships.iter().enumerate() {
+            outgoing[index[&relationship.from_id]].push(edge);
+            incoming[index[&relationship.to_id]].push(edge);
+        }
+
+        Graph {
+            entities,
+            relationships,
+            index,
+            outgoing,
+            incoming,
+        }
+    }
+
+    /// Load every entity and relationship
+    pub async fn load(pool: &PgPool) -> Result<Graph, MyError> {
+        let entities = sqlx::query_as::<_, Entity>("SELECT * FROM entities ORDER BY id")
+            .fetch_all(pool)
+            .await?;
+        let relationships =
+            sqlx::query_as::<_, Relationship>("SELECT * FROM relationships ORDER BY id")
+                .fetch_all(pool)
+                .await?;
+
+        Ok(Graph::new(entities, relationships))
+    }
+
+    /// Load the given entities and only the relationships between them
+    pub async fn load_subset(pool: &PgPool, ids: &[DbBigSerial]) -> Result<Graph, MyError> {
+        let entities =
+            sqlx::query_as::<_, Entity>("SELECT * FROM entities WHERE id = ANY($1) ORDER BY id")
+                .bind(ids)
+                .fetch_all(pool)
+                .await?;
+        let relationships = sqlx::query_as::<_, Relationship>(
+            "SELECT * FROM relationships WHERE from_id = ANY($1) AND to_id = ANY($1) ORDER BY id",
+        )
+        .bind(ids)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Graph::new(entities, relationships))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// Position of the entity with database id `id`
+    pub fn index_of(&self, id: DbBigSerial) -> Option<usize> {
+        self.index.get(&id).copied()
+    }
+
+    /// Positions of the entities that `node` depends on
+    pub fn dependencies(&self, node: usize) -> impl Iterator<Item = usize> + '_ {
+        self.outgoing[node]
+            .iter()
+            .map(|&edge| self.index[&self.relationships[edge].to_id])
+    }
+
+    /// Positions of the entities that depend on `node`
+    pub fn dependents(&self, node: usize) -> impl Iterator<Item = usize> + '_ {
+        self.incoming[node]
+            .iter()
+            .map(|&edge| self.index[&self.relationships[edge].from_id])
+    }
+
+    /// Edges as `(from, to)` pairs of entity positions
+    pub fn edges(&self) -> Vec<(usize, usize)> {
+        self.relationships
+            .iter()
+            .map(|r| (self.index[&r.from_id], self.index[&r.to_id]))
+            .collect()
+    }
+
+    /// Groups of entity positions that depend on each other in a cycle
+    pub fn cycles(&self) -> Vec<Vec<usize>> {
+        analysis::cyclic_components(self.len(), &self.edges())
+    }
+
+    /// Entity types numbered for `failure`, equal numbers for equal types
+    fn kinds(&self) -> Vec<usize> {
+        let mut numbers: HashMap<&str, usize> = HashMap::new();
+        self.entities
+            .iter()
+            .map(|entity| {
+                let next = numbers.len();
+                *numbers.entry(entity.entity_type.as_str()).or_insert(next)
+            })
+            .collect()
+    }
+
+    /// Consumers and dependency types of every entity position, to cascade failures with
+    /// [`failure::Dependencies::cascade`]
+    pub fn failure_dependencies(&self) -> failure::Dependencies {
+        failure::Dependencies::new(self.len(), &self.edges(), &self.kinds())
+    }
+
+    /// For each entity position, its consumers having no other dependency of its type
+    pub fn sole_dependency_of(&self) -> Vec<Vec<usize>> {
+        failure::sole_dependency_of(self.len(), &self.edges(), &self.kinds())
+    }
+
+    /// The availability and latency declared on each entity, see [`simulation::Figures`]
+    pub fn declared_figures(&self) -> Vec<simulation::Figures> {
+        self.entities
+            .iter()
+            .map(|entity| simulation::Figures {
+                availability: entity.availability / 100.0,
+                p95_millis: entity.p95_millis as f64,
+                p99_millis: entity.p99_millis as f64,
+            })
+            .collect()
+    }
+
+    /// Figures of every entity position once those in `changed` replace the declared ones,
+    /// see [`simulation::simulate`]
+    pub fn simulate(
+        &self,
+        changed: &HashMap<usize, simulation::Figures>,
+    ) -> Vec<simulation::Figures> {
+        simulation::simulate(
+            self.len(),
+            &self.edges(),
+            &self.kinds(),
+            &self.declared_figures(),
+            changed,
+        )
+    }
+
+    /// Entity positions that would split the graph in two if removed
+    pub fn articulation_points(&self) -> Vec<usize> {
+        analysis::articulation_points(self.len(), &self.edges())
+    }
+
+    /// Edges between entity positions marked in `through`, with their positions in
+    /// `relationships`
+    fn edges_through(&self, through: &[bool]) -> (Vec<(usize, usize)>, Vec<usize>) {
+        self.edges()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, (from, to))| through[*from] && through[*to])
+            .map(|(position, edge)| (edge, position))
+            .unzip()
+    }
+
+    /// Positions in `relationships` along a path with the fewest hops from `from` to `to`,
+    /// passing only entity positions marked in `through`
+    pub fn shortest_path(&self, from: usize, to: usize, through: &[bool]) -> Option<Vec<usize>> {
+        let (edges, positions) = self.edges_through(through);
+        paths::shortest_path(self.len(), &edges, from, to)
+            .map(|path| path.into_iter().map(|edge| positions[edge]).collect())
+    }
+
+    /// Positions in `relationships` along each path from `from` to `to` visiting no entity
+    /// twice and only entity positions marked in `through`, see [`paths::simple_paths`]
+    pub fn simple_paths(
+        &self,
+        from: usize,
+        to: usize,
+        through: &[bool],
+        max_hops: usize,
+        limit: usize,
+        max_steps: usize,
+    ) -> (Vec<Vec<usize>>, bool) {
+        let (edges, positions) = self.edges_through(through);
+        let (paths, truncated) =
+            paths::simple_paths(self.len(), &edges, from, to, max_hops, limit, max_steps);
+        let paths = paths
+            .into_iter()
+            .map(|path| path.into_iter().map(|edge| positions[edge]).collect())
+            .collect();
+        (paths, truncated)
+    }
+}
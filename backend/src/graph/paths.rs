@@ -0,0 +1,171 @@
+//! Paths following `(from, to)` edges between two nodes
+//!
+//! Paths are returned as the positions of the edges along them, so parallel edges between the
+//! same nodes give distinct paths and callers can report what each hop is.
+
+use std::collections::VecDeque;
+
+/// Edge positions leaving each node, in edge order
+fn adjacency(n: usize, edges: &[(usize, usize)]) -> Vec<Vec<usize>> {
+    let mut adjacency = vec![vec![]; n];
+    for (edge, &(from, _)) in edges.iter().enumerate() {
+        adjacency[from].push(edge);
+    }
+    adjacency
+}
+
+/// A path with the fewest edges by breadth-first search, empty when `from` is `to`
+pub fn shortest_path(
+    n: usize,
+    edges: &[(usize, usize)],
+    from: usize,
+    to: usize,
+) -> Option<Vec<usize>> {
+    let adjacency = adjacency(n, edges);
+    // Edge reaching each node on the first visit
+    let mut via: Vec<Option<usize>> = vec![None; n];
+    let mut seen = vec![false; n];
+    let mut queue = VecDeque::from([from]);
+    seen[from] = true;
+
+    while let Some(node) = queue.pop_front() {
+        if node == to {
+            let mut path = vec![];
+            let mut at = to;
+            while let Some(edge) = via[at] {
+                path.push(edge);
+                at = edges[edge].0;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for &edge in &adjacency[node] {
+            let next = edges[edge].1;
+            if !seen[next] {
+                seen[next] = true;
+                via[next] = Some(edge);
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+/// Paths visiting no node twice with at most `max_edges` edges, shortest first
+///
+/// Paths are searched one length at a time so that stopping after `limit` paths keeps the
+/// shortest, the flag tells whether more were left unexplored. Their number grows exponentially
+/// with the length, so the search also stops once `max_steps` edges have been examined.
+pub fn simple_paths(
+    n: usize,
+    edges: &[(usize, usize)],
+    from: usize,
+    to: usize,
+    max_edges: usize,
+    limit: usize,
+    max_steps: usize,
+) -> (Vec<Vec<usize>>, bool) {
+    if from == to {
+        return (vec![vec![]], false);
+    }
+    let adjacency = adjacency(n, edges);
+
+    // Fewest edges from each node to `to`, so branches that cannot arrive in time are not walked
+    let mut reverse = vec![vec![]; n];
+    for &(from, to) in edges {
+        reverse[to].push(from);
+    }
+    let mut remaining = vec![usize::MAX; n];
+    remaining[to] = 0;
+    let mut queue = VecDeque::from([to]);
+    while let Some(node) = queue.pop_front() {
+        for &previous in &reverse[node] {
+            if remaining[previous] == usize::MAX {
+                remaining[previous] = remaining[node] + 1;
+                queue.push_back(previous);
+            }
+        }
+    }
+
+    let mut paths = vec![];
+    let mut steps = 0;
+    for length in remaining[from]..=max_edges {
+        let mut on_path = vec![false; n];
+        on_path[from] = true;
+        let mut path: Vec<usize> = vec![];
+        // (node, position in its adjacency list)
+        let mut work = vec![(from, 0)];
+
+        while let Some(&mut (node, ref mut child)) = work.last_mut() {
+            let Some(&edge) = adjacency[node].get(*child) else {
+                work.pop();
+                on_path[node] = false;
+                path.pop();
+                continue;
+            };
+            *child += 1;
+            steps += 1;
+            if steps > max_steps {
+                return (paths, true);
+            }
+
+            let next = edges[edge].1;
+            if on_path[next] || remaining[next].saturating_add(path.len() + 1) > length {
+                continue;
+            }
+            if next == to {
+                if path.len() + 1 == length {
+                    if paths.len() == limit {
+                        return (paths, true);
+                    }
+                    let mut found = path.clone();
+                    found.push(edge);
+                    paths.push(found);
+                }
+                continue;
+            }
+            on_path[next] = true;
+            path.push(edge);
+            work.push((next, 0));
+        }
+    }
+
+    (paths, false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // 0 -> 1 -> 3, 0 -> 2 -> 3, 0 -> 3 twice, 3 -> 0 closes a cycle, 4 is unreachable
+    const EDGES: [(usize, usize); 7] = [(0, 1), (1, 3), (0, 2), (2, 3), (0, 3), (0, 3), (3, 0)];
+
+    #[test]
+    fn shortest_path_by_hops() {
+        assert_eq!(shortest_path(5, &EDGES, 0, 3), Some(vec![4]));
+        assert_eq!(shortest_path(5, &EDGES, 1, 2), Some(vec![1, 6, 2]));
+        assert_eq!(shortest_path(5, &EDGES, 2, 2), Some(vec![]));
+        assert_eq!(shortest_path(5, &EDGES, 0, 4), None);
+    }
+
+    #[test]
+    fn simple_paths_are_bounded() {
+        let (paths, truncated) = simple_paths(5, &EDGES, 0, 3, 5, 10, 100);
+        assert_eq!(paths, vec![vec![4], vec![5], vec![0, 1], vec![2, 3]]);
+        assert!(!truncated);
+
+        let (paths, _) = simple_paths(5, &EDGES, 0, 3, 1, 10, 100);
+        assert_eq!(paths, vec![vec![4], vec![5]]);
+
+        let (paths, truncated) = simple_paths(5, &EDGES, 0, 3, 5, 3, 100);
+        assert_eq!(paths, vec![vec![4], vec![5], vec![0, 1]]);
+        assert!(truncated);
+
+        assert_eq!(simple_paths(5, &EDGES, 0, 4, 5, 10, 100), (vec![], false));
+
+        // Running out of steps keeps what was found so far
+        let (paths, truncated) = simple_paths(5, &EDGES, 0, 3, 5, 10, 4);
+        assert_eq!(paths, vec![vec![4], vec![5]]);
+        assert!(truncated);
+    }
+}
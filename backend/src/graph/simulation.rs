@@ -0,0 +1,176 @@
+//! What-if propagation of changed availability and latency to consumers
+//!
+//! The figures declared on an entity are taken as what it achieves with its dependencies as
+//! declared. When dependencies change, a consumer's figures move by the change in what its
+//! dependencies provide, with the same rule as `failure`: dependencies of different kinds are
+//! in series, those of the same kind parallel.
+//!
+//! - Availability is scaled, per kind, by the new over the old chance that at least one
+//!   dependency of that kind is up.
+//! - Latency grows, per kind, by the change in the slowest dependency of that kind still up,
+//!   as calls to different kinds are made one after another.
+//!
+//! Nodes are evaluated dependencies first. Within a dependency cycle the members are
+//! re-evaluated once per member so changes travel around it.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::graph::analysis::strongly_connected_components;
+
+/// Availability as a fraction, latency in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Figures {
+    pub availability: f64,
+    pub p95_millis: f64,
+    pub p99_millis: f64,
+}
+
+/// What the dependencies of one kind provide to a consumer
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Provided {
+    /// Chance that at least one is up
+    availability: f64,
+    p95_millis: f64,
+    p99_millis: f64,
+}
+
+fn provided(dependencies: &[usize], figures: &[Figures]) -> Provided {
+    let unavailable: f64 = dependencies
+        .iter()
+        .map(|&dependency| 1.0 - figures[dependency].availability)
+        .product();
+
+    // The slowest dependency still answering, or of all when none are
+    let up: Vec<usize> = dependencies
+        .iter()
+        .copied()
+        .filter(|&dependency| figures[dependency].availability > 0.0)
+        .collect();
+    let answering = if up.is_empty() { dependencies } else { &up };
+    let slowest = |latency: fn(&Figures) -> f64| {
+        answering
+            .iter()
+            .map(|&dependency| latency(&figures[dependency]))
+            .fold(0.0, f64::max)
+    };
+
+    Provided {
+        availability: 1.0 - unavailable,
+        p95_millis: slowest(|f| f.p95_millis),
+        p99_millis: slowest(|f| f.p99_millis),
+    }
+}
+
+/// Figures of every node once those in `changed` replace the declared ones
+pub fn simulate(
+    n: usize,
+    edges: &[(usize, usize)],
+    kinds: &[usize],
+    declared: &[Figures],
+    changed: &HashMap<usize, Figures>,
+) -> Vec<Figures> {
+    let edges: HashSet<(usize, usize)> = edges
+        .iter()
+        .copied()
+        .filter(|(from, to)| from != to)
+        .collect();
+
+    // Dependencies of each node grouped by kind
+    let mut groups: Vec<BTreeMap<usize, Vec<usize>>> = vec![BTreeMap::new(); n];
+    for &(from, to) in &edges {
+        groups[from].entry(kinds[to]).or_default().push(to);
+    }
+    let before: Vec<Vec<Provided>> = groups
+        .iter()
+        .map(|group| {
+            group
+                .values()
+                .map(|dependencies| provided(dependencies, declared))
+                .collect()
+        })
+        .collect();
+
+    let mut figures = declared.to_vec();
+    for (&node, &change) in changed {
+        figures[node] = change;
+    }
+
+    let pairs: Vec<(usize, usize)> = edges.into_iter().collect();
+    for component in strongly_connected_components(n, &pairs) {
+        for _ in 0..component.len() {
+            for &node in &component {
+                if changed.contains_key(&node) {
+                    continue;
+                }
+                let mut next = declared[node];
+                for (dependencies, before) in groups[node].values().zip(&before[node]) {
+                    let after = provided(dependencies, &figures);
+                    if before.availability > 0.0 {
+                        next.availability *= after.availability / before.availability;
+                    }
+                    next.p95_millis += after.p95_millis - before.p95_millis;
+                    next.p99_millis += after.p99_millis - before.p99_millis;
+                }
+                next.availability = next.availability.clamp(0.0, 1.0);
+                figures[node] = next;
+            }
+        }
+    }
+
+    figures
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn figures(availability: f64, p99_millis: f64) -> Figures {
+        Figures {
+            availability,
+            p95_millis: p99_millis / 2.0,
+            p99_millis,
+        }
+    }
+
+    // 0 depends on 1 and 2 of kind 1 (parallel) and 3 of kind 2, 4 depends on 0
+    const EDGES: [(usize, usize); 4] = [(0, 1), (0, 2), (0, 3), (4, 0)];
+    const KINDS: [usize; 5] = [0, 1, 1, 2, 0];
+
+    fn declared() -> Vec<Figures> {
+        vec![
+            figures(0.99, 100.0),
+            figures(0.9, 20.0),
+            figures(0.9, 40.0),
+            figures(0.999, 10.0),
+            figures(0.98, 200.0),
+        ]
+    }
+
+    #[test]
+    fn unchanged_figures_stay_declared() {
+        let simulated = simulate(5, &EDGES, &KINDS, &declared(), &HashMap::new());
+        assert_eq!(simulated, declared());
+    }
+
+    #[test]
+    fn failures_and_latency_reach_consumers() {
+        // losing one of the parallel pair leaves 0.9 instead of 0.99 of that kind
+        let changed = HashMap::from([(1, figures(0.0, 20.0))]);
+        let simulated = simulate(5, &EDGES, &KINDS, &declared(), &changed);
+        assert!((simulated[0].availability - 0.99 * 0.9 / 0.99).abs() < 1e-9);
+        assert!((simulated[4].availability - 0.98 * 0.9 / 0.99).abs() < 1e-9);
+        assert_eq!(simulated[3], declared()[3]);
+
+        // the slower of the pair gets slower, in series with the rest
+        let changed = HashMap::from([(2, figures(0.9, 90.0))]);
+        let simulated = simulate(5, &EDGES, &KINDS, &declared(), &changed);
+        assert_eq!(simulated[0].p99_millis, 150.0);
+        assert_eq!(simulated[4].p99_millis, 250.0);
+
+        // losing the only dependency of a kind takes the consumers down
+        let changed = HashMap::from([(3, figures(0.0, 10.0))]);
+        let simulated = simulate(5, &EDGES, &KINDS, &declared(), &changed);
+        assert_eq!(simulated[0].availability, 0.0);
+        assert_eq!(simulated[4].availability, 0.0);
+    }
+}
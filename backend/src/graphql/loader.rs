@@ -0,0 +1,116 @@
+//! Batched loading of entities and relationships
+//!
+//! Resolvers running concurrently within a request ask for single keys, the `DataLoader`
+//! collects them into one query per key type so that listing the neighbours of many entities
+//! costs one query per traversal level rather than one per entity.
+
+use std::{collections::HashMap, sync::Arc};
+
+use async_graphql::dataloader::Loader;
+
+use crate::{
+    persistence::PersistenceState,
+    webserver::{DbBigSerial, entities::Entity, relationships::Relationship},
+};
+
+/// An entity by id
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EntityId(pub DbBigSerial);
+
+/// The relationships from an entity to its dependencies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Outgoing(pub DbBigSerial);
+
+/// The relationships to an entity from its dependents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Incoming(pub DbBigSerial);
+
+/// Loads from the current pool, so a pool resized by a reload is picked up by the next request
+pub struct GraphLoader {
+    db: PersistenceState,
+}
+
+impl GraphLoader {
+    pub fn new(db: PersistenceState) -> Self {
+        GraphLoader { db }
+    }
+
+    /// Relationships whose `column` is one of `ids`, grouped by that end, every id present
+    async fn relationships(
+        &self,
+        column: &str,
+        ids: Vec<DbBigSerial>,
+    ) -> Result<HashMap<DbBigSerial, Vec<Relationship>>, Arc<sqlx::Error>> {
+        let relationships = sqlx::query_as::<_, Relationship>(&format!(
+            "SELECT * FROM relationships WHERE {column} = ANY($1) ORDER BY id"
+        ))
+        .bind(&ids)
+        .fetch_all(&self.db.pool())
+        .await?;
+
+        let mut grouped: HashMap<DbBigSerial, Vec<Relationship>> =
+            ids.into_iter().map(|id| (id, vec![])).collect();
+        for relationship in relationships {
+            let id = match column {
+                "from_id" => relationship.from_id,
+                _ => relationship.to_id,
+            };
+            grouped.entry(id).or_default().push(relationship);
+        }
+        Ok(grouped)
+    }
+}
+
+impl Loader<EntityId> for GraphLoader {
+    type Value = Entity;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(&self, keys: &[EntityId]) -> Result<HashMap<EntityId, Entity>, Self::Error> {
+        let ids: Vec<DbBigSerial> = keys.iter().map(|key| key.0).collect();
+        let entities = sqlx::query_as::<_, Entity>("SELECT * FROM entities WHERE id = ANY($1)")
+            .bind(&ids)
+            .fetch_all(&self.db.pool())
+            .await?;
+
+        Ok(entities
+            .into_iter()
+            .filter_map(|entity| entity.id.map(|id| (EntityId(id), entity)))
+            .collect())
+    }
+}
+
+impl Loader<Outgoing> for GraphLoader {
+    type Value = Vec<Relationship>;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(
+        &self,
+        keys: &[Outgoing],
+    ) -> Result<HashMap<Outgoing, Vec<Relationship>>, Self::Error> {
+        let ids = keys.iter().map(|key| key.0).collect();
+        Ok(self
+            .relationships("from_id", ids)
+            .await?
+            .into_iter()
+            .map(|(id, relationships)| (Outgoing(id), relationships))
+            .collect())
+    }
+}
+
+impl Loader<Incoming> for GraphLoader {
+    type Value = Vec<Relationship>;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(
+        &self,
+        keys: &[Incoming],
+    ) -> Result<HashMap<Incoming, Vec<Relationship>>, Self::Error> {
+        let ids = keys.iter().map(|key| key.0).collect();
+        Ok(self
+            .relationships("to_id", ids)
+            .await?
+            .into_iter()
+            .map(|(id, relationships)| (Incoming(id), relationships))
+            .collect())
+    }
+}
@@ -0,0 +1,622 @@
+//! GraphQL API over the entity graph
+//!
+//! The REST endpoints return flat records, so assembling the neighbourhood of an entity takes a
+//! request per hop. The GraphQL schema exposes `Entity` and `Relationship` with
+//! `dependencies`/`dependents` fields walking the graph breadth first up to a `depth`, so a
+//! client can fetch an entity, what it depends on and what depends on those in one request.
+//!
+//! Entities and relationships are fetched through a per-request `DataLoader` (`loader`), which
+//! batches the keys requested by concurrently resolving fields into one query and caches the
+//! results for the rest of the request. Query nesting, complexity, traversal depth and page
+//! sizes are bounded by `GraphqlConfig`.
+
+use std::{collections::HashSet, future::Future};
+
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Json, Object, Request, Result, Schema, SimpleObject,
+    dataloader::{DataLoader, HashMapCache},
+};
+use serde::Deserialize;
+use sqlx::QueryBuilder;
+
+use crate::{
+    persistence::PersistenceState,
+    webserver::{
+        DbBigSerial, entities::Entity, labels::LabelSelector, relationships::Relationship,
+    },
+};
+
+pub mod loader;
+
+use loader::{EntityId, GraphLoader, Incoming, Outgoing};
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct GraphqlConfig {
+    /// Largest `depth` accepted by `dependencies` and `dependents`
+    pub max_traversal_depth: usize,
+    /// Most entities one `dependencies` or `dependents` walk may reach
+    pub max_traversal_nodes: usize,
+    /// Deepest nesting of fields in a query
+    pub max_query_depth: usize,
+    /// Largest number of fields a query may resolve, counting the fields of a paged list once
+    /// per item of the page `size`
+    pub max_complexity: usize,
+    /// Largest `size` of a page
+    pub max_page_size: i64,
+    /// Serve GraphiQL at `GET /graphql`
+    pub graphiql: bool,
+}
+
+impl Default for GraphqlConfig {
+    fn default() -> Self {
+        GraphqlConfig {
+            max_traversal_depth: 5,
+            max_traversal_nodes: 1000,
+            max_query_depth: 16,
+            max_complexity: 1000,
+            max_page_size: 100,
+            graphiql: false,
+        }
+    }
+}
+
+pub type CaptureSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+type GraphDataLoader = DataLoader<GraphLoader, HashMapCache>;
+
+pub fn schema(config: &GraphqlConfig) -> CaptureSchema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .limit_depth(config.max_query_depth)
+        .limit_complexity(config.max_complexity)
+        .data(config.clone())
+        .finish()
+}
+
+/// Attach the database and a fresh loader, whose cache lasts as long as the request
+pub fn with_loader(request: Request, db: PersistenceState) -> Request {
+    let loader: GraphDataLoader = DataLoader::with_cache(
+        GraphLoader::new(db.clone()),
+        tokio::spawn,
+        HashMapCache::default(),
+    );
+    request.data(loader).data(db)
+}
+
+fn selector(text: Option<String>) -> Result<LabelSelector> {
+    Ok(text
+        .as_deref()
+        .unwrap_or_default()
+        .parse::<LabelSelector>()?)
+}
+
+/// Complexity of a paged list field, its fields counted for every item of the page
+fn page_complexity(size: i64, child_complexity: usize) -> usize {
+    (size.max(1) as usize).saturating_mul(child_complexity)
+}
+
+/// Rows to skip and return for a page, within the configured page size
+fn page_bounds(ctx: &Context<'_>, page: i64, size: i64) -> Result<(i64, i64)> {
+    let max = ctx.data::<GraphqlConfig>()?.max_page_size;
+    if page < 0 || !(1..=max).contains(&size) {
+        return Err(
+            format!("page must not be negative and size must be between 1 and {max}").into(),
+        );
+    }
+    Ok((page.saturating_mul(size), size))
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    async fn entity(&self, ctx: &Context<'_>, id: DbBigSerial) -> Result<Option<Entity>> {
+        let loader = ctx.data::<GraphDataLoader>()?;
+        Ok(loader.load_one(EntityId(id)).await?)
+    }
+
+    /// Entities ordered by id, optionally restricted by label selector, type and owning team
+    #[graphql(complexity = "page_complexity(size, child_complexity)")]
+    async fn entities(
+        &self,
+        ctx: &Context<'_>,
+        selector: Option<String>,
+        #[graphql(name = "type")] entity_type: Option<String>,
+        owner: Option<DbBigSerial>,
+        #[graphql(default)] page: i64,
+        #[graphql(default = 20)] size: i64,
+    ) -> Result<Vec<Entity>> {
+        let (offset, limit) = page_bounds(ctx, page, size)?;
+        let selector = self::selector(selector)?;
+
+        let mut query = QueryBuilder::new("SELECT * FROM entities WHERE TRUE");
+        selector.push_sql(&mut query, "labels");
+        if let Some(entity_type) = entity_type {
+            query.push(" AND type = ").push_bind(entity_type);
+        }
+        if let Some(owner) = owner {
+            query.push(" AND owner_team_id = ").push_bind(owner);
+        }
+        query
+            .push(" ORDER BY id LIMIT ")
+            .push_bind(limit)
+            .push(" OFFSET ")
+            .push_bind(offset);
+
+        let db = ctx.data::<PersistenceState>()?;
+        let entities = query
+            .build_query_as::<Entity>()
+            .fetch_all(&db.pool())
+            .await?;
+
+        let loader = ctx.data::<GraphDataLoader>()?;
+        loader
+            .feed_many(
+                entities
+                    .iter()
+                    .filter_map(|entity| Some((EntityId(entity.id?), entity.clone()))),
+            )
+            .await;
+        Ok(entities)
+    }
+
+    async fn relationship(
+        &self,
+        ctx: &Context<'_>,
+        id: DbBigSerial,
+    ) -> Result<Option<Relationship>> {
+        let db = ctx.data::<PersistenceState>()?;
+        Ok(
+            sqlx::query_as::<_, Relationship>("SELECT * FROM relationships WHERE id = $1")
+                .bind(id)
+                .fetch_optional(&db.pool())
+                .await?,
+        )
+    }
+
+    /// Relationships ordered by id, optionally restricted by label selector, type and ends
+    #[allow(clippy::too_many_arguments)]
+    #[graphql(complexity = "page_complexity(size, child_complexity)")]
+    async fn relationships(
+        &self,
+        ctx: &Context<'_>,
+        selector: Option<String>,
+        #[graphql(name = "type")] relationship_type: Option<String>,
+        from_id: Option<DbBigSerial>,
+        to_id: Option<DbBigSerial>,
+        #[graphql(default)] page: i64,
+        #[graphql(default = 20)] size: i64,
+    ) -> Result<Vec<Relationship>> {
+        let (offset, limit) = page_bounds(ctx, page, size)?;
+        let selector = self::selector(selector)?;
+
+        let mut query = QueryBuilder::new("SELECT * FROM relationships WHERE TRUE");
+        selector.push_sql(&mut query, "labels");
+        if let Some(relationship_type) = relationship_type {
+            query
+                .push(" AND relationship_type = ")
+                .push_bind(relationship_type);
+        }
+        if let Some(from_id) = from_id {
+            query.push(" AND from_id = ").push_bind(from_id);
+        }
+        if let Some(to_id) = to_id {
+            query.push(" AND to_id = ").push_bind(to_id);
+        }
+        query
+            .push(" ORDER BY id LIMIT ")
+            .push_bind(limit)
+            .push(" OFFSET ")
+            .push_bind(offset);
+
+        let db = ctx.data::<PersistenceState>()?;
+        Ok(query
+            .build_query_as::<Relationship>()
+            .fetch_all(&db.pool())
+            .await?)
+    }
+}
+
+/// An entity reached by walking the graph, with the relationship it was reached through
+#[derive(SimpleObject)]
+pub struct Neighbour {
+    /// Hops from the starting entity, 1 for direct neighbours
+    pub depth: usize,
+    pub entity: Entity,
+    pub relationship: Relationship,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Direction {
+    /// From consumers to their dependencies
+    Down,
+    /// From dependencies to their consumers
+    Up,
+}
+
+impl Direction {
+    fn next(self, relationship: &Relationship) -> DbBigSerial {
+        match self {
+            Direction::Down => relationship.to_id,
+            Direction::Up => relationship.from_id,
+        }
+    }
+}
+
+/// Walk breadth first from `start` for up to `depth` hops, following only relationships of
+/// `types` when any are given
+///
+/// Each entity is reached once, at its shortest distance, so cycles end the walk. `edges` is
+/// asked for the relationships of a whole level at once. Returns `None` once more than
+/// `max_nodes` entities are reached.
+async fn traverse<F, Fut, E>(
+    start: DbBigSerial,
+    direction: Direction,
+    depth: usize,
+    types: &[String],
+    max_nodes: usize,
+    mut edges: F,
+) -> Result<Option<Vec<(usize, Relationship)>>, E>
+where
+    F: FnMut(Vec<DbBigSerial>) -> Fut,
+    Fut: Future<Output = Result<Vec<(DbBigSerial, Vec<Relationship>)>, E>>,
+{
+    let mut seen = HashSet::from([start]);
+    let mut frontier = vec![start];
+    let mut found = vec![];
+
+    for level in 1..=depth {
+        let mut level_edges = edges(frontier.clone()).await?;
+        level_edges.sort_by_key(|(id, _)| frontier.iter().position(|f| f == id));
+
+        let mut next = vec![];
+        for relationship in level_edges.into_iter().flat_map(|(_, edges)| edges) {
+            if !types.is_empty() && !types.contains(&relationship.relationship_type) {
+                continue;
+            }
+            let reached = direction.next(&relationship);
+            if seen.insert(reached) {
+                if found.len() == max_nodes {
+                    return Ok(None);
+                }
+                next.push(reached);
+                found.push((level, relationship));
+            }
+        }
+        if next.is_empty() {
+            break;
+        }
+        frontier = next;
+    }
+    Ok(Some(found))
+}
+
+/// Resolve the neighbours of `start`, filtered and paged
+#[allow(clippy::too_many_arguments)]
+async fn neighbours(
+    ctx: &Context<'_>,
+    start: DbBigSerial,
+    direction: Direction,
+    depth: usize,
+    types: Vec<String>,
+    entity_type: Option<String>,
+    selector: Option<String>,
+    page: i64,
+    size: i64,
+) -> Result<Vec<Neighbour>> {
+    let config = ctx.data::<GraphqlConfig>()?;
+    let max = config.max_traversal_depth;
+    if !(1..=max).contains(&depth) {
+        return Err(format!("depth must be between 1 and {max}").into());
+    }
+    let max_nodes = config.max_traversal_nodes;
+    let (offset, limit) = page_bounds(ctx, page, size)?;
+    let selector = self::selector(selector)?;
+    let loader = ctx.data::<GraphDataLoader>()?;
+
+    let found = traverse(
+        start,
+        direction,
+        depth,
+        &types,
+        max_nodes,
+        |ids| async move {
+            Ok::<_, async_graphql::Error>(match direction {
+                Direction::Down => loader
+                    .load_many(ids.into_iter().map(Outgoing))
+                    .await?
+                    .into_iter()
+                    .map(|(key, edges)| (key.0, edges))
+                    .collect(),
+                Direction::Up => loader
+                    .load_many(ids.into_iter().map(Incoming))
+                    .await?
+                    .into_iter()
+                    .map(|(key, edges)| (key.0, edges))
+                    .collect(),
+            })
+        },
+    )
+    .await?
+    .ok_or_else(|| {
+        format!("more than {max_nodes} entities within depth {depth}, lower depth or set types")
+    })?;
+
+    let entities = loader
+        .load_many(found.iter().map(|(_, r)| EntityId(direction.next(r))))
+        .await?;
+
+    Ok(found
+        .into_iter()
+        .filter_map(|(depth, relationship)| {
+            let entity = entities.get(&EntityId(direction.next(&relationship)))?;
+            Some(Neighbour {
+                depth,
+                entity: entity.clone(),
+                relationship,
+            })
+        })
+        .filter(|n| {
+            entity_type
+                .as_ref()
+                .is_none_or(|t| &n.entity.entity_type == t)
+        })
+        .filter(|n| selector.matches(&n.entity.labels))
+        .skip(offset as usize)
+        .take(limit as usize)
+        .collect())
+}
+
+#[Object]
+impl Entity {
+    async fn id(&self) -> DbBigSerial {
+        self.id.unwrap_or_default()
+    }
+
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[graphql(name = "type")]
+    async fn entity_type(&self) -> &str {
+        &self.entity_type
+    }
+
+    async fn p99_millis(&self) -> i32 {
+        self.p99_millis
+    }
+
+    async fn p95_millis(&self) -> i32 {
+        self.p95_millis
+    }
+
+    /// Declared availability as a percentage
+    async fn availability(&self) -> f64 {
+        self.availability
+    }
+
+    async fn throughput_rps(&self) -> i32 {
+        self.throughput_rps
+    }
+
+    async fn x(&self) -> Option<i32> {
+        self.x
+    }
+
+    async fn y(&self) -> Option<i32> {
+        self.y
+    }
+
+    async fn attributes(&self) -> Json<&serde_json::Value> {
+        Json(&self.attributes)
+    }
+
+    async fn labels(&self) -> Json<&crate::webserver::labels::Labels> {
+        Json(&self.labels)
+    }
+
+    async fn owner_team_id(&self) -> Option<DbBigSerial> {
+        self.owner_team_id
+    }
+
+    /// Entities this one depends on, directly or within `depth` hops
+    ///
+    /// `types` limits the relationships followed, `type` and `selector` filter the entities
+    /// returned without stopping the walk through others.
+    #[allow(clippy::too_many_arguments)]
+    #[graphql(complexity = "page_complexity(size, child_complexity)")]
+    async fn dependencies(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 1)] depth: usize,
+        #[graphql(default)] types: Vec<String>,
+        #[graphql(name = "type")] entity_type: Option<String>,
+        selector: Option<String>,
+        #[graphql(default)] page: i64,
+        #[graphql(default = 20)] size: i64,
+    ) -> Result<Vec<Neighbour>> {
+        neighbours(
+            ctx,
+            self.id.unwrap_or_default(),
+            Direction::Down,
+            depth,
+            types,
+            entity_type,
+            selector,
+            page,
+            size,
+        )
+        .await
+    }
+
+    /// Entities depending on this one, directly or within `depth` hops
+    ///
+    /// Filters as for `dependencies`.
+    #[allow(clippy::too_many_arguments)]
+    #[graphql(complexity = "page_complexity(size, child_complexity)")]
+    async fn dependents(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 1)] depth: usize,
+        #[graphql(default)] types: Vec<String>,
+        #[graphql(name = "type")] entity_type: Option<String>,
+        selector: Option<String>,
+        #[graphql(default)] page: i64,
+        #[graphql(default = 20)] size: i64,
+    ) -> Result<Vec<Neighbour>> {
+        neighbours(
+            ctx,
+            self.id.unwrap_or_default(),
+            Direction::Up,
+            depth,
+            types,
+            entity_type,
+            selector,
+            page,
+            size,
+        )
+        .await
+    }
+}
+
+#[Object]
+impl Relationship {
+    async fn id(&self) -> DbBigSerial {
+        self.id.unwrap_or_default()
+    }
+
+    async fn from_id(&self) -> DbBigSerial {
+        self.from_id
+    }
+
+    async fn to_id(&self) -> DbBigSerial {
+        self.to_id
+    }
+
+    #[graphql(name = "type")]
+    async fn relationship_type(&self) -> &str {
+        &self.relationship_type
+    }
+
+    async fn attributes(&self) -> Json<&serde_json::Value> {
+        Json(&self.attributes)
+    }
+
+    async fn labels(&self) -> Json<&crate::webserver::labels::Labels> {
+        Json(&self.labels)
+    }
+
+    /// The consumer
+    async fn from(&self, ctx: &Context<'_>) -> Result<Option<Entity>> {
+        let loader = ctx.data::<GraphDataLoader>()?;
+        Ok(loader.load_one(EntityId(self.from_id)).await?)
+    }
+
+    /// The dependency
+    async fn to(&self, ctx: &Context<'_>) -> Result<Option<Entity>> {
+        let loader = ctx.data::<GraphDataLoader>()?;
+        Ok(loader.load_one(EntityId(self.to_id)).await?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn relationship(from_id: DbBigSerial, to_id: DbBigSerial, kind: &str) -> Relationship {
+        Relationship {
+            id: Some(from_id * 100 + to_id),
+            from_id,
+            to_id,
+            relationship_type: kind.into(),
+            attributes: serde_json::json!({}),
+            labels: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn traverse_level_by_level() {
+        // 1 -> 2 -> 3 -> 1 and 2 -> 4 (hosted_on)
+        let graph = [
+            relationship(1, 2, "depends_on"),
+            relationship(2, 3, "depends_on"),
+            relationship(3, 1, "depends_on"),
+            relationship(2, 4, "hosted_on"),
+        ];
+        let mut calls = vec![];
+        let mut walk = async |direction: Direction, depth: usize, types: &[String], max: usize| {
+            traverse(1, direction, depth, types, max, |ids: Vec<DbBigSerial>| {
+                calls.push(ids.clone());
+                let edges: HashMap<DbBigSerial, Vec<Relationship>> = ids
+                    .iter()
+                    .map(|id| {
+                        let edges = graph
+                            .iter()
+                            .filter(|r| match direction {
+                                Direction::Down => r.from_id == *id,
+                                Direction::Up => r.to_id == *id,
+                            })
+                            .cloned()
+                            .collect();
+                        (*id, edges)
+                    })
+                    .collect();
+                async move { Ok::<_, ()>(edges.into_iter().collect()) }
+            })
+            .await
+            .unwrap()
+            .map(|found| {
+                found
+                    .into_iter()
+                    .map(|(depth, r)| (depth, direction.next(&r)))
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(walk(Direction::Down, 1, &[], 10).await, Some(vec![(1, 2)]));
+        assert_eq!(
+            walk(Direction::Down, 5, &[], 10).await,
+            Some(vec![(1, 2), (2, 3), (2, 4)])
+        );
+        assert_eq!(
+            walk(Direction::Down, 5, &["depends_on".into()], 10).await,
+            Some(vec![(1, 2), (2, 3)])
+        );
+        assert_eq!(
+            walk(Direction::Up, 5, &[], 10).await,
+            Some(vec![(1, 3), (2, 2)])
+        );
+
+        // Reaching more entities than allowed gives up
+        assert_eq!(walk(Direction::Down, 5, &[], 2).await, None);
+
+        // One call per level, the cycle back to 1 ends the walk
+        assert_eq!(calls[1..4], [vec![1], vec![2], vec![3, 4]]);
+    }
+
+    #[tokio::test]
+    async fn limits_are_checked_before_resolving() {
+        let schema = schema(&GraphqlConfig {
+            max_query_depth: 4,
+            ..Default::default()
+        });
+        let sdl = schema.sdl();
+        assert!(sdl.contains("dependencies(depth: Int! = 1"));
+        assert!(sdl.contains("dependents(depth: Int! = 1"));
+
+        let response = schema
+            .execute(
+                "{ entity(id: 1) { dependencies { entity { dependents { entity { id } } } } } }",
+            )
+            .await;
+        assert!(response.errors[0].message.contains("nested too deep"));
+
+        // Every item of a page counts, 100 entities of 100 dependencies each is too much
+        let response = schema
+            .execute("{ entities(size: 100) { dependencies(size: 100) { depth } } }")
+            .await;
+        assert!(response.errors[0].message.contains("too complex"));
+    }
+}
@@ -1,5 +1,6 @@
+use chrono::{DateTime, Utc};
 use reqwest::Client;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_with::DurationSeconds;
 use serde_with::serde_as;
 use std::time::Duration;
@@ -8,10 +9,15 @@ use tracing::{error, info};
 
 use url::Url;
 
-use crate::error::MyError;
+use crate::{error::MyError, forwarding::Forward};
 
+/// URLs probed before the web service binds (`preflights`) and once shutdown starts (`shutdowns`)
+///
+/// `fails` failed requests are allowed across all the URLs of a stage, each followed by a wait
+/// of `timeout` seconds before retrying.
 #[serde_as]
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
 pub struct Checks {
     #[serde_as(as = "DurationSeconds<u64>")]
     pub timeout: Duration,
@@ -20,53 +26,155 @@ pub struct Checks {
     pub shutdowns: Vec<Url>,
 }
 
+impl Default for Checks {
+    fn default() -> Self {
+        Checks {
+            timeout: Duration::from_secs(5),
+            fails: 2,
+            preflights: vec![],
+            shutdowns: vec![],
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct UrlCheck {
+    pub url: Url,
+    pub ok: bool,
+    pub attempts: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Outcome of running the preflight or shutdown URLs
+#[derive(Serialize, Debug, Clone)]
+pub struct CheckReport {
+    pub ok: bool,
+    pub retries_remaining: u32,
+    pub urls: Vec<UrlCheck>,
+    pub completed_at: DateTime<Utc>,
+}
+
 impl Checks {
-    pub async fn preflight(&self, client: &Client) -> Result<u32, MyError> {
+    pub fn client(&self) -> Result<Client, MyError> {
+        Ok(Client::builder().timeout(self.timeout).build()?)
+    }
+
+    /// Request each URL until it answers with a success status or the allowed fails run out
+    async fn probe(&self, client: &Client, stage: &str, urls: &[Url]) -> CheckReport {
         let mut fails = self.fails;
-        for preflight in self.preflights.iter() {
-            info!("Checking preflight: {}", preflight);
-            while fails > 0 && client.get(preflight.clone()).send().await.is_err() {
-                info!(
-                    "Failed preflight: {} retrying in {} secs (fail count {}/{})",
-                    preflight,
-                    self.timeout.as_secs(),
-                    fails,
-                    self.fails
-                );
-                sleep(self.timeout).await;
-                fails -= 1;
+        let mut checks = vec![];
+
+        for url in urls {
+            info!("Checking {}: {}", stage, url);
+            let mut check = UrlCheck {
+                url: url.clone(),
+                ok: false,
+                attempts: 0,
+                error: None,
+            };
+            while !check.ok && fails > 0 {
+                check.attempts += 1;
+                match client
+                    .get(url.clone())
+                    .forward_headers()
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                {
+                    Ok(_) => {
+                        check.ok = true;
+                        check.error = None;
+                    }
+                    Err(err) => {
+                        info!(
+                            "Failed {}: {} retrying in {} secs (fail count {}/{})",
+                            stage,
+                            url,
+                            self.timeout.as_secs(),
+                            fails,
+                            self.fails
+                        );
+                        check.error = Some(err.to_string());
+                        fails -= 1;
+                        if fails > 0 {
+                            sleep(self.timeout).await;
+                        }
+                    }
+                }
             }
+            checks.push(check);
         }
-        if fails > 0 {
-            info!("Preflight success, {} retries remaining", fails);
-            Ok(fails)
+
+        CheckReport {
+            ok: checks.iter().all(|check| check.ok),
+            retries_remaining: fails,
+            urls: checks,
+            completed_at: Utc::now(),
+        }
+    }
+
+    pub async fn preflight(&self, client: &Client) -> CheckReport {
+        let report = self.probe(client, "preflight", &self.preflights).await;
+        if report.ok {
+            info!(
+                "Preflight success, {} retries remaining",
+                report.retries_remaining
+            );
         } else {
             error!("Preflight FAIL");
-            Err(MyError::PreflightCheck)
         }
+        report
     }
-    pub async fn shutdown(&self, client: &Client) -> Result<u32, MyError> {
-        let mut fails = self.fails;
-        for shutdown in self.shutdowns.iter() {
-            info!("Checking shutdown: {}", shutdown);
-            while fails > 0 && client.get(shutdown.clone()).send().await.is_err() {
-                info!(
-                    "Failed shutdown: {} retrying in {} secs (fail count {}/{})",
-                    shutdown,
-                    self.timeout.as_secs(),
-                    fails,
-                    self.fails
-                );
-                sleep(self.timeout).await;
-                fails -= 1;
-            }
-        }
-        if fails > 0 {
-            info!("Shutdown success, {} retries remaining", fails);
-            Ok(fails)
+
+    pub async fn shutdown(&self, client: &Client) -> CheckReport {
+        let report = self.probe(client, "shutdown", &self.shutdowns).await;
+        if report.ok {
+            info!(
+                "Shutdown success, {} retries remaining",
+                report.retries_remaining
+            );
         } else {
             error!("Shutdown FAIL");
-            Err(MyError::ShutdownCheck)
         }
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{Router, http::StatusCode, routing::get};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn preflight_waits_for_success() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route("/up", get(|| async { "ok" }))
+            .route("/down", get(|| async { StatusCode::SERVICE_UNAVAILABLE }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let url = |path: &str| Url::parse(&format!("http://{address}{path}")).unwrap();
+        let checks = Checks {
+            timeout: Duration::from_millis(10),
+            fails: 2,
+            preflights: vec![url("/up")],
+            shutdowns: vec![url("/up"), url("/down")],
+        };
+        let client = checks.client().unwrap();
+
+        let preflight = checks.preflight(&client).await;
+        assert!(preflight.ok);
+        assert_eq!(preflight.retries_remaining, 2);
+
+        let shutdown = checks.shutdown(&client).await;
+        assert!(!shutdown.ok);
+        assert!(shutdown.urls[0].ok);
+        assert_eq!(shutdown.urls[1].attempts, 2);
+        assert!(shutdown.urls[1].error.is_some());
+
+        assert!(Checks::default().preflight(&client).await.ok);
     }
 }
@@ -0,0 +1,240 @@
+//! Liveness and readiness of the service
+//!
+//! HaMs serves `/hams/alive` and `/hams/ready`, but this service only uses `Hams::new`, `start`,
+//! `stop` and the Prometheus hooks of hamsrs and registers no checks with it. The checks that
+//! depend on this service's resources, Postgres being reachable and its migrations being
+//! current, and the flip to not ready once shutdown starts, are kept here and served by the
+//! web service under `/health` (see `webserver::health`), which the chart's readiness probe
+//! targets. Liveness stays on HaMs.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Instant,
+};
+
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::{hams::CheckReport, persistence::MIGRATOR, reload::ReloadReport};
+
+/// Latest results of the configured preflight and shutdown checks
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct LifecycleChecks {
+    pub preflight: Option<CheckReport>,
+    pub shutdown: Option<CheckReport>,
+}
+
+/// Shared health state, cloned into `MyState`
+#[derive(Debug, Clone, Default)]
+pub struct Health {
+    shutting_down: Arc<AtomicBool>,
+    checks: Arc<RwLock<LifecycleChecks>>,
+    reload: Arc<RwLock<Option<ReloadReport>>>,
+}
+
+impl Health {
+    /// Report not ready from now on so load balancers stop sending new requests
+    pub fn set_shutting_down(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    pub fn set_preflight(&self, report: CheckReport) {
+        self.checks.write().unwrap().preflight = Some(report);
+    }
+
+    pub fn set_shutdown(&self, report: CheckReport) {
+        self.checks.write().unwrap().shutdown = Some(report);
+    }
+
+    pub fn checks(&self) -> LifecycleChecks {
+        self.checks.read().unwrap().clone()
+    }
+
+    pub fn set_reload(&self, report: ReloadReport) {
+        *self.reload.write().unwrap() = Some(report);
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DatabaseCheck {
+    pub ok: bool,
+    pub latency_millis: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Applied migrations compared with those embedded in the binary
+#[derive(Debug, Serialize, Default, PartialEq)]
+pub struct MigrationCheck {
+    pub ok: bool,
+    /// Embedded but not yet applied
+    pub pending: Vec<i64>,
+    /// Applied but recorded as unsuccessful
+    pub failed: Vec<i64>,
+    /// Applied with a different checksum to the embedded file
+    pub modified: Vec<i64>,
+    /// Applied but not embedded, e.g. by a newer release
+    pub unknown: Vec<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A row of `_sqlx_migrations`
+#[derive(Debug, sqlx::FromRow)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub success: bool,
+    pub checksum: Vec<u8>,
+}
+
+/// Compare embedded `(version, checksum)` pairs with the applied migrations
+pub fn compare_migrations(
+    embedded: &[(i64, &[u8])],
+    applied: &[AppliedMigration],
+) -> MigrationCheck {
+    let applied_by_version: HashMap<i64, &AppliedMigration> =
+        applied.iter().map(|m| (m.version, m)).collect();
+    let embedded_versions: HashMap<i64, &[u8]> = embedded.iter().copied().collect();
+
+    let mut check = MigrationCheck::default();
+    for &(version, checksum) in embedded {
+        match applied_by_version.get(&version) {
+            None => check.pending.push(version),
+            Some(applied) if !applied.success => check.failed.push(version),
+            Some(applied) if applied.checksum != checksum => check.modified.push(version),
+            Some(_) => {}
+        }
+    }
+    check.unknown = applied
+        .iter()
+        .filter(|m| !embedded_versions.contains_key(&m.version))
+        .map(|m| m.version)
+        .collect();
+
+    check.ok = check.pending.is_empty() && check.failed.is_empty() && check.modified.is_empty();
+    check
+}
+
+pub async fn check_database(pool: &PgPool) -> DatabaseCheck {
+    let start = Instant::now();
+    let result = sqlx::query("SELECT 1").execute(pool).await;
+
+    DatabaseCheck {
+        ok: result.is_ok(),
+        latency_millis: start.elapsed().as_millis(),
+        error: result.err().map(|err| err.to_string()),
+    }
+}
+
+pub async fn check_migrations(pool: &PgPool) -> MigrationCheck {
+    let embedded: Vec<(i64, &[u8])> = MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .map(|m| (m.version, m.checksum.as_ref()))
+        .collect();
+
+    let applied = sqlx::query_as::<_, AppliedMigration>(
+        "SELECT version, success, checksum FROM _sqlx_migrations ORDER BY version",
+    )
+    .fetch_all(pool)
+    .await;
+
+    match applied {
+        Ok(applied) => compare_migrations(&embedded, &applied),
+        Err(err) => MigrationCheck {
+            pending: embedded.iter().map(|&(version, _)| version).collect(),
+            error: Some(err.to_string()),
+            ..Default::default()
+        },
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub shutting_down: bool,
+    pub database: DatabaseCheck,
+    pub migrations: MigrationCheck,
+    pub checks: LifecycleChecks,
+    /// Latest config reload, listing changes that wait for a restart
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reload: Option<ReloadReport>,
+}
+
+/// Ready when not shutting down, the database answers and every migration is applied
+pub async fn readiness(health: &Health, pool: &PgPool) -> Readiness {
+    let shutting_down = health.is_shutting_down();
+    let database = check_database(pool).await;
+    let migrations = check_migrations(pool).await;
+
+    Readiness {
+        ready: !shutting_down && database.ok && migrations.ok,
+        shutting_down,
+        database,
+        migrations,
+        checks: health.checks(),
+        reload: health.reload.read().unwrap().clone(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn applied(version: i64, success: bool, checksum: &[u8]) -> AppliedMigration {
+        AppliedMigration {
+            version,
+            success,
+            checksum: checksum.to_vec(),
+        }
+    }
+
+    #[test]
+    fn migrations_must_all_be_applied_unchanged() {
+        let embedded: [(i64, &[u8]); 3] = [(1, b"a"), (2, b"b"), (3, b"c")];
+
+        let current = compare_migrations(
+            &embedded,
+            &[
+                applied(1, true, b"a"),
+                applied(2, true, b"b"),
+                applied(3, true, b"c"),
+            ],
+        );
+        assert!(current.ok);
+
+        let check = compare_migrations(
+            &embedded,
+            &[
+                applied(1, true, b"x"),
+                applied(2, false, b"b"),
+                applied(9, true, b"z"),
+            ],
+        );
+        assert!(!check.ok);
+        assert_eq!(check.modified, vec![1]);
+        assert_eq!(check.failed, vec![2]);
+        assert_eq!(check.pending, vec![3]);
+        assert_eq!(check.unknown, vec![9]);
+
+        // a newer release having migrated ahead does not make this one unready
+        let ahead = compare_migrations(
+            &embedded[..2],
+            &[
+                applied(1, true, b"a"),
+                applied(2, true, b"b"),
+                applied(3, true, b"c"),
+            ],
+        );
+        assert!(ahead.ok);
+        assert_eq!(ahead.unknown, vec![3]);
+    }
+}
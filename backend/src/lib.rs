@@ -2,22 +2,43 @@ use std::{ffi::c_void, sync::Arc};
 
 use axum_prometheus::metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
 use hamsrs::Hams;
-use prometheus::{IntGauge, Registry};
-use tokio::sync::Mutex;
+use prometheus::Registry;
+use tokio::{
+    sync::Mutex,
+    time::{Instant, timeout_at},
+};
 use tokio_util::sync::CancellationToken;
+use tracing::{error, warn};
 
 use crate::{
-    config::MyConfig, error::MyError, persistence::PersistenceState, tokio_tools::run_in_tokio,
+    config::MyConfig,
+    error::MyError,
+    health::Health,
+    persistence::PersistenceState,
+    reload::{ConfigFiles, LiveConfig, reload_cancellable},
+    tokio_tools::run_in_tokio,
     webserver::start_app_api,
 };
 
-use metrics::{prometheus_response_free, prometheus_response_mystate};
+use metrics::{
+    DomainMetrics, prometheus_response_free, prometheus_response_mystate, refresh_cancellable,
+};
 
 pub mod config;
 pub mod error;
+pub mod forwarding;
+pub mod graph;
+pub mod graphql;
 pub mod hams;
+pub mod health;
+pub mod logging;
 mod metrics;
 pub mod persistence;
+pub mod reload;
+pub mod risk;
+pub mod scraper;
+pub mod slo;
+pub mod telemetry;
 pub mod tokio_tools;
 pub mod webserver;
 
@@ -33,6 +54,9 @@ pub struct MyState {
     pub count_good: Arc<Mutex<usize>>,
     pub count_fail: Arc<Mutex<usize>>,
     registry: Registry,
+    metrics: DomainMetrics,
+    health: Health,
+    live: LiveConfig,
     prometheus_handle: Arc<PrometheusHandle>,
 }
 
@@ -41,8 +65,7 @@ impl MyState {
         let db_state = PersistenceState::new(&config.persistence).await?;
         let registry = Registry::new();
 
-        let hello_counter = IntGauge::new("my_counter", "A counter for my application")?;
-        registry.register(Box::new(hello_counter.clone()))?;
+        let metrics = DomainMetrics::register(&registry)?;
 
         let metric_handle = PrometheusBuilder::new()
             // .set_buckets_for_metric(
@@ -59,24 +82,57 @@ impl MyState {
             count_good: Arc::new(Mutex::new(0)),
             count_fail: Arc::new(Mutex::new(0)),
             registry,
+            metrics,
+            health: Health::default(),
+            live: LiveConfig::new(config),
             // prometheus_handle: Arc::new(RwLock::new(None)),
             prometheus_handle: Arc::new(metric_handle),
         })
     }
 }
 
-pub fn service_start(config: &MyConfig) -> Result<(), MyError> {
+pub fn service_start(config: &MyConfig, files: ConfigFiles) -> Result<(), MyError> {
     let ct = CancellationToken::new();
 
-    run_in_tokio(&config.runtime, service_cancellable(ct, config))
+    run_in_tokio(&config.runtime, async {
+        let signals = tokio_tools::cancel_on_signal(ct.clone());
+        tokio::spawn(async move {
+            if let Err(err) = signals.await {
+                error!("Signal handling stopped: {err}");
+            }
+        });
+        service_cancellable(ct, config, files).await
+    })
 }
 
-pub async fn service_cancellable(ct: CancellationToken, config: &MyConfig) -> Result<(), MyError> {
+pub async fn service_cancellable(
+    ct: CancellationToken,
+    config: &MyConfig,
+    files: ConfigFiles,
+) -> Result<(), MyError> {
     let state = MyState::new(config).await?;
 
-    let pool_pg = state.db_state.pool_pg.clone();
-
-    // Initialise liveness here
+    let checks = state.config.checks.clone();
+    let checks_client = checks.client()?;
+
+    // Report not ready as soon as shutdown starts then run the shutdown checks while draining,
+    // noting when the drain budget runs out
+    let health = state.health.clone();
+    let shutdown = ct.clone();
+    let drain_timeout = state.config.webservice.drain_timeout;
+    let shutdown_checks = tokio::spawn({
+        let checks = checks.clone();
+        let client = checks_client.clone();
+        async move {
+            shutdown.cancelled().await;
+            let drain_deadline = Instant::now() + drain_timeout;
+            health.set_shutting_down();
+            let report = checks.shutdown(&client).await;
+            let ok = report.ok;
+            health.set_shutdown(report);
+            (ok, drain_deadline)
+        }
+    });
 
     let mut config = state.config.hams.clone();
 
@@ -94,14 +150,66 @@ pub async fn service_cancellable(ct: CancellationToken, config: &MyConfig) -> Re
 
     hams.start().unwrap();
 
-    let server = start_app_api(state.clone(), pool_pg, ct.clone());
+    let refresh = refresh_cancellable(ct.clone(), state.clone());
+    tokio::spawn(async move {
+        if let Err(err) = refresh.await {
+            error!("Domain metrics refresh stopped: {err}");
+        }
+    });
+
+    let reload = reload_cancellable(ct.clone(), state.clone(), files);
+    tokio::spawn(async move {
+        if let Err(err) = reload.await {
+            error!("Config reload stopped: {err}");
+        }
+    });
+
+    if state.config.scraper.enabled {
+        let scraper = scraper::scrape_cancellable(
+            ct.clone(),
+            state.db_state.clone(),
+            state.config.scraper.clone(),
+        );
+        tokio::spawn(async move {
+            if let Err(err) = scraper.await {
+                error!("Prometheus scraper stopped: {err}");
+            }
+        });
+    }
+
+    // Wait for dependencies such as an auth server before accepting requests
+    let preflight = checks.preflight(&checks_client).await;
+    let preflight_ok = preflight.ok;
+    state.health.set_preflight(preflight);
 
-    server.await?;
+    let served = if preflight_ok {
+        start_app_api(state.clone(), ct.clone()).await
+    } else {
+        Err(MyError::PreflightCheck)
+    };
+
+    // The server also returns early when it fails to bind, so make sure shutdown has started
+    ct.cancel();
+    let (shutdown_ok, drain_deadline) = shutdown_checks
+        .await
+        .unwrap_or_else(|_| (false, Instant::now()));
 
     hams.stop()?;
     hams.deregister_prometheus()?;
 
-    ct.cancel();
+    // Background tasks stop on the cancelled token, so the pool can close once drained, but
+    // connections still held by abandoned requests must not hold up the exit
+    if timeout_at(drain_deadline, state.db_state.pool().close())
+        .await
+        .is_err()
+    {
+        warn!("Drain deadline passed before the database pool closed");
+    }
+
+    served?;
+    if !shutdown_ok {
+        return Err(MyError::ShutdownCheck);
+    }
 
     Ok(())
 }
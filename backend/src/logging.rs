@@ -0,0 +1,220 @@
+//! Logging setup with a filter and format that can be replaced while running
+//!
+//! The filter comes from `logging.filter` in the config when set, otherwise from the
+//! `CAPTURE_LOG` environment variable, defaulting to `warn`. Logs are written as text or, for
+//! indexing by a log pipeline, as one JSON object per line (`logging.format`, otherwise the
+//! `--log-format` given on the command line).
+//!
+//! Events within a request carry the fields of its span: `method`, `uri`, `matched_path`,
+//! `request_id` and `user` from the configured headers, and once answered `status` and
+//! `latency_ms`.
+
+use std::sync::OnceLock;
+
+use axum::http::HeaderMap;
+use clap::ValueEnum;
+use serde::Deserialize;
+use tracing::{Span, Subscriber, level_filters::LevelFilter};
+use tracing_subscriber::{
+    EnvFilter, Layer, fmt::MakeWriter, layer::SubscriberExt, registry::LookupSpan, reload,
+    util::SubscriberInitExt,
+};
+
+use crate::{error::MyError, telemetry};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Filter directives such as `info,sqlx=warn`, overriding `CAPTURE_LOG`
+    pub filter: Option<String>,
+    /// Overrides `--log-format`
+    pub format: Option<LogFormat>,
+    /// Header logged as `request_id`
+    pub request_id_header: String,
+    /// Header set by the authenticating proxy logged as `user`
+    pub user_header: String,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            filter: None,
+            format: None,
+            request_id_header: "x-request-id".into(),
+            user_header: "x-forwarded-user".into(),
+        }
+    }
+}
+
+impl LoggingConfig {
+    /// Record the request id and user of an inbound request on its span
+    pub fn record_request(&self, span: &Span, headers: &HeaderMap) {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        if let Some(request_id) = header(&self.request_id_header) {
+            span.record("request_id", request_id);
+        }
+        if let Some(user) = header(&self.user_header) {
+            span.record("user", user);
+        }
+    }
+}
+
+type Reload = Box<dyn Fn(EnvFilter) -> Result<(), reload::Error> + Send + Sync>;
+
+/// Replace the filter of the text log output
+static FILTER: OnceLock<Reload> = OnceLock::new();
+/// Replace the filter of the JSON log output
+static JSON_FILTER: OnceLock<Reload> = OnceLock::new();
+/// Replace the filter of the spans exported by `telemetry`
+static TELEMETRY_FILTER: OnceLock<Reload> = OnceLock::new();
+/// Format used when the config does not choose one
+static FORMAT: OnceLock<LogFormat> = OnceLock::new();
+
+fn builder() -> tracing_subscriber::filter::Builder {
+    EnvFilter::builder().with_default_directive(LevelFilter::WARN.into())
+}
+
+/// The JSON log output, one object per line with the event's fields at the top level and the
+/// fields of its innermost span under `span`
+fn json_layer<S, W>(make_writer: W) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    tracing_subscriber::fmt::layer()
+        .json()
+        .flatten_event(true)
+        .with_current_span(true)
+        .with_span_list(false)
+        .with_writer(make_writer)
+}
+
+/// Install the global subscriber, logging in `format` as filtered by `CAPTURE_LOG` and
+/// exporting no spans
+pub fn init(format: LogFormat) -> Result<(), MyError> {
+    let (filter, handle) = reload::Layer::new(EnvFilter::new("off"));
+    let (json_filter, json_handle) = reload::Layer::new(EnvFilter::new("off"));
+    let (telemetry_filter, telemetry_handle) = reload::Layer::new(EnvFilter::new("off"));
+
+    tracing_subscriber::registry()
+        .with(telemetry::layer().with_filter(telemetry_filter))
+        .with(tracing_subscriber::fmt::layer().with_filter(filter))
+        .with(json_layer(std::io::stdout).with_filter(json_filter))
+        .init();
+
+    FILTER
+        .set(Box::new(move |filter| handle.reload(filter)))
+        .ok();
+    JSON_FILTER
+        .set(Box::new(move |filter| json_handle.reload(filter)))
+        .ok();
+    TELEMETRY_FILTER
+        .set(Box::new(move |filter| telemetry_handle.reload(filter)))
+        .ok();
+    FORMAT.set(format).ok();
+
+    apply(&LoggingConfig::default())
+}
+
+fn replace(reload: &OnceLock<Reload>, filter: EnvFilter) -> Result<(), MyError> {
+    match reload.get() {
+        Some(reload) => {
+            reload(filter).map_err(|_| MyError::Message("Log filter could not be reloaded"))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Replace the filter and format with the configured ones, or `CAPTURE_LOG` and the command
+/// line format when not configured
+pub fn apply(config: &LoggingConfig) -> Result<(), MyError> {
+    let filter = match &config.filter {
+        Some(directives) => builder()
+            .parse(directives)
+            .map_err(|err| MyError::Validation(format!("logging.filter: {err}")))?,
+        None => builder().with_env_var("CAPTURE_LOG").from_env()?,
+    };
+    let format = config.format.or(FORMAT.get().copied()).unwrap_or_default();
+
+    let (on, off) = match format {
+        LogFormat::Text => (&FILTER, &JSON_FILTER),
+        LogFormat::Json => (&JSON_FILTER, &FILTER),
+    };
+    replace(off, EnvFilter::new("off"))?;
+    replace(on, filter)
+}
+
+/// Choose which spans and events are exported as traces
+pub fn apply_telemetry_filter(directives: &str) -> Result<(), MyError> {
+    let filter = builder()
+        .parse(directives)
+        .map_err(|err| MyError::Validation(format!("telemetry.filter: {err}")))?;
+    replace(&TELEMETRY_FILTER, filter)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use axum::http::HeaderValue;
+    use serde_json::Value;
+    use tracing::info;
+
+    use super::*;
+
+    #[test]
+    fn json_events_carry_request_fields() {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let writer = output.clone();
+        let subscriber =
+            tracing_subscriber::registry().with(json_layer(move || WriteTo(writer.clone())));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", HeaderValue::from_static("abc-123"));
+        headers.insert("x-forwarded-user", HeaderValue::from_static("jane"));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!(
+                "request",
+                matched_path = "/entities/{id}",
+                request_id = tracing::field::Empty,
+                user = tracing::field::Empty,
+                status = tracing::field::Empty,
+                latency_ms = tracing::field::Empty,
+            );
+            LoggingConfig::default().record_request(&span, &headers);
+            span.record("status", 200);
+            span.record("latency_ms", 12);
+            span.in_scope(|| info!("finished processing request"));
+        });
+
+        let output = output.lock().unwrap();
+        let line: Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(line["message"], "finished processing request");
+        assert_eq!(line["span"]["matched_path"], "/entities/{id}");
+        assert_eq!(line["span"]["request_id"], "abc-123");
+        assert_eq!(line["span"]["user"], "jane");
+        assert_eq!(line["span"]["status"], 200);
+        assert_eq!(line["span"]["latency_ms"], 12);
+    }
+
+    struct WriteTo(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for WriteTo {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+}
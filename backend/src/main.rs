@@ -7,16 +7,18 @@ use clap::{Parser, Subcommand};
 
 use ffi_log2::log_param;
 use hamsrs::hams_logger_init;
-use service_capture::config::MyConfig;
+use service_capture::config::{MyConfig, check};
+use service_capture::logging::LogFormat;
 use service_capture::persistence::start_db_migrate;
+use service_capture::reload::ConfigFiles;
+use service_capture::tokio_tools::run_in_tokio;
 use service_capture::{
     error::MyError,
     // persistence::{start_db_backup, start_db_check_tables, start_db_migrate},
     // webserver::service_start,
 };
-use tracing::level_filters::LevelFilter;
-use tracing::{Level, debug, error, info};
-use tracing_subscriber::EnvFilter;
+use service_capture::{logging, telemetry};
+use tracing::{debug, error, info};
 
 use service_capture::{NAME, VERSION, service_start};
 
@@ -24,6 +26,9 @@ use service_capture::{NAME, VERSION, service_start};
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    /// Log output format, unless set by `logging.format` in the config
+    #[arg(long, value_enum, global = true, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
     #[command(subcommand)]
     command: Commands,
 }
@@ -73,6 +78,7 @@ enum Commands {
         #[arg(value_name = "BACKUPDIR")]
         backup_dir: PathBuf,
     },
+    /// Validate the config and print the effective values with their sources
     ConfigCheck {
         /// Sets a custom config file
         #[arg(short, long, value_name = "FILE")]
@@ -80,22 +86,16 @@ enum Commands {
         /// Sets a custom secrets directory
         #[arg(short, long, value_name = "DIR", default_value = PathBuf::from("secrets").into_os_string())]
         secrets: PathBuf,
+        /// Also check the database is reachable
+        #[arg(long)]
+        connect: bool,
     },
 }
 
 fn main() -> Result<ExitCode, MyError> {
-    // let env = EnvFilter::from_env("CAPTURE_LOG");
-    let env = EnvFilter::builder()
-        .with_default_directive(LevelFilter::WARN.into())
-        .with_env_var("CAPTURE_LOG")
-        .from_env()?;
-
-    tracing_subscriber::fmt()
-        .with_max_level(Level::INFO)
-        .with_env_filter(env)
-        .init();
-
     let args = Cli::parse();
+    logging::init(args.log_format)?;
+
     match args.command {
         Commands::Version => {
             println!("{NAME} Version: :{VERSION}");
@@ -115,15 +115,27 @@ fn main() -> Result<ExitCode, MyError> {
                 }
             };
 
-            let config: MyConfig = MyConfig::figment(&config_yaml, secrets)
-                .extract()
-                .unwrap_or_else(|err| {
-                    error!("Config file {config:?} failed with error \n{err:#?}");
-                    panic!("Config failed to load");
-                });
+            let files = ConfigFiles {
+                config: config.clone(),
+                secrets: secrets.clone(),
+            };
+
+            let config: MyConfig = match MyConfig::figment(&config_yaml, secrets).extract() {
+                Ok(config) => config,
+                Err(errors) => {
+                    for err in errors {
+                        error!("Config file {config:?}: {err}");
+                    }
+                    error!("Config failed to load, run config-check for details");
+                    return Ok(ExitCode::FAILURE);
+                }
+            };
 
             debug!("Loaded config {:?}", config);
 
+            logging::apply(&config.logging)?;
+            telemetry::install(&config.telemetry)?;
+
             if config.persistence.db.automigrate {
                 info!(
                     "Auto-migrating database: {}",
@@ -132,7 +144,9 @@ fn main() -> Result<ExitCode, MyError> {
                 start_db_migrate(&config.persistence)?;
             }
 
-            service_start(&config)?;
+            let served = service_start(&config, files);
+            telemetry::shutdown();
+            served?;
         }
         Commands::DbCheck { config, secrets } => {
             info!("Starting {NAME} for {VERSION}");
@@ -145,14 +159,29 @@ fn main() -> Result<ExitCode, MyError> {
 
             // start_db_check_tables(&config.persistence)?;
         }
-        Commands::ConfigCheck { config, secrets } => {
+        Commands::ConfigCheck {
+            config,
+            secrets,
+            connect,
+        } => {
             info!("Config check {NAME} for {VERSION}");
 
             let config_yaml = std::fs::read_to_string(config.clone())?;
 
-            let config: MyConfig = MyConfig::figment(&config_yaml, secrets).extract()?;
+            let (config, mut report) = check::check(&config_yaml, &secrets);
 
-            debug!("Loaded config {:#?}", config);
+            if connect && let Some(config) = &config {
+                let connected = run_in_tokio(&config.runtime, async {
+                    Ok(check::check_connect(&config.persistence).await)
+                })?;
+                report.problems.extend(connected);
+            }
+
+            println!("{report}");
+
+            if !report.is_ok() {
+                return Ok(ExitCode::FAILURE);
+            }
         }
         Commands::Migrate { config, secrets } => {
             info!("Starting Migration for {NAME}:{VERSION}");
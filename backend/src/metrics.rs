@@ -1,9 +1,166 @@
-use std::ffi::{CString, c_char, c_void};
+use std::{
+    ffi::{CString, c_char, c_void},
+    time::Duration,
+};
+
+use prometheus::{Encoder, Gauge, IntGauge, IntGaugeVec, Opts, Registry};
+use sqlx::PgPool;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::{MyState, error::MyError, graph::Graph};
+
+/// How often the gauges that need database queries are recomputed
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Domain gauges exported through the HaMs prometheus hook
+///
+/// Counts that need queries are refreshed in the background every [REFRESH_INTERVAL] as the
+/// hook is called synchronously from HaMs; pool and response gauges are read on each scrape.
+#[derive(Debug, Clone)]
+pub struct DomainMetrics {
+    entities: IntGaugeVec,
+    relationships: IntGaugeVec,
+    cycles: IntGauge,
+    missing_slo: IntGauge,
+    pool_connections: IntGaugeVec,
+    pool_max_connections: IntGauge,
+    pool_utilisation: Gauge,
+    responses: IntGaugeVec,
+}
+
+impl DomainMetrics {
+    pub fn register(registry: &Registry) -> Result<DomainMetrics, MyError> {
+        let metrics = DomainMetrics {
+            entities: IntGaugeVec::new(
+                Opts::new("capture_entities", "Entities by type"),
+                &["type"],
+            )?,
+            relationships: IntGaugeVec::new(
+                Opts::new("capture_relationships", "Relationships by type"),
+                &["type"],
+            )?,
+            cycles: IntGauge::new(
+                "capture_dependency_cycles",
+                "Groups of entities that depend on each other in a cycle",
+            )?,
+            missing_slo: IntGauge::new(
+                "capture_entities_missing_slo",
+                "Entities without a declared availability, p95 or p99",
+            )?,
+            pool_connections: IntGaugeVec::new(
+                Opts::new("capture_db_pool_connections", "Database pool connections"),
+                &["state"],
+            )?,
+            pool_max_connections: IntGauge::new(
+                "capture_db_pool_max_connections",
+                "Maximum connections the database pool may open",
+            )?,
+            pool_utilisation: Gauge::new(
+                "capture_db_pool_utilisation",
+                "Fraction of the maximum database connections in use",
+            )?,
+            responses: IntGaugeVec::new(
+                Opts::new(
+                    "capture_http_responses",
+                    "HTTP responses since start by outcome, fail being a 5xx status",
+                ),
+                &["outcome"],
+            )?,
+        };
+
+        registry.register(Box::new(metrics.entities.clone()))?;
+        registry.register(Box::new(metrics.relationships.clone()))?;
+        registry.register(Box::new(metrics.cycles.clone()))?;
+        registry.register(Box::new(metrics.missing_slo.clone()))?;
+        registry.register(Box::new(metrics.pool_connections.clone()))?;
+        registry.register(Box::new(metrics.pool_max_connections.clone()))?;
+        registry.register(Box::new(metrics.pool_utilisation.clone()))?;
+        registry.register(Box::new(metrics.responses.clone()))?;
+
+        Ok(metrics)
+    }
+
+    /// Recompute the gauges derived from the stored graph
+    pub async fn refresh(&self, pool: &PgPool) -> Result<(), MyError> {
+        let entities =
+            sqlx::query_as::<_, (String, i64)>("SELECT type, COUNT(*) FROM entities GROUP BY type")
+                .fetch_all(pool)
+                .await?;
+        self.entities.reset();
+        for (entity_type, count) in entities {
+            self.entities.with_label_values(&[&entity_type]).set(count);
+        }
+
+        let relationships = sqlx::query_as::<_, (String, i64)>(
+            "SELECT relationship_type, COUNT(*) FROM relationships GROUP BY relationship_type",
+        )
+        .fetch_all(pool)
+        .await?;
+        self.relationships.reset();
+        for (relationship_type, count) in relationships {
+            self.relationships
+                .with_label_values(&[&relationship_type])
+                .set(count);
+        }
+
+        let missing_slo = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM entities WHERE availability <= 0 OR p95_millis <= 0 OR p99_millis <= 0",
+        )
+        .fetch_one(pool)
+        .await?;
+        self.missing_slo.set(missing_slo);
+
+        let graph = Graph::load(pool).await?;
+        self.cycles.set(graph.cycles().len() as i64);
+
+        Ok(())
+    }
 
-use prometheus::{Encoder, Registry};
-use tracing::info;
+    /// Read the gauges that are cheap enough to compute on every scrape
+    fn observe(&self, state: &MyState) {
+        let pool = &state.db_state.pool();
+        let size = pool.size() as i64;
+        let idle = pool.num_idle() as i64;
+        let max = pool.options().get_max_connections() as i64;
+
+        self.pool_connections
+            .with_label_values(&["active"])
+            .set(size - idle);
+        self.pool_connections.with_label_values(&["idle"]).set(idle);
+        self.pool_max_connections.set(max);
+        if max > 0 {
+            self.pool_utilisation.set((size - idle) as f64 / max as f64);
+        }
+
+        if let Ok(good) = state.count_good.try_lock() {
+            self.responses
+                .with_label_values(&["good"])
+                .set(*good as i64);
+        }
+        if let Ok(fail) = state.count_fail.try_lock() {
+            self.responses
+                .with_label_values(&["fail"])
+                .set(*fail as i64);
+        }
+    }
+}
 
-use crate::MyState;
+/// Refresh the domain gauges every [REFRESH_INTERVAL] until cancelled
+pub async fn refresh_cancellable(ct: CancellationToken, state: MyState) -> Result<(), MyError> {
+    let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = ct.cancelled() => return Ok(()),
+            _ = interval.tick() => {
+                if let Err(err) = state.metrics.refresh(&state.db_state.pool()).await {
+                    warn!("Refreshing domain metrics failed: {err}");
+                }
+            }
+        }
+    }
+}
 
 #[unsafe(no_mangle)]
 pub extern "C" fn prometheus_response(ptr: *const c_void) -> *const c_char {
@@ -29,6 +186,8 @@ pub extern "C" fn prometheus_response(ptr: *const c_void) -> *const c_char {
 pub extern "C" fn prometheus_response_mystate(ptr: *const c_void) -> *const c_char {
     let state = unsafe { &*(ptr as *const MyState) };
 
+    state.metrics.observe(state);
+
     let encoder = prometheus::TextEncoder::new();
     let mut buffer = Vec::new();
 
@@ -1,5 +1,8 @@
 use std::path::Path;
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
 
 use ::parquet::data_type::{ByteArrayType, Int64Type};
 use ::parquet::file::properties::WriterProperties;
@@ -7,6 +10,7 @@ use ::parquet::file::writer::SerializedFileWriter;
 use parquet::generate_parquet_schema_from_table;
 use serde::Deserialize;
 use sqlx::Row;
+use sqlx::migrate::Migrator;
 use sqlx::{Column, Executor, PgPool, postgres::PgPoolOptions};
 use std::time::Duration;
 use tokio_util::sync::CancellationToken;
@@ -18,7 +22,10 @@ mod parquet;
 use crate::config::UrlWithUsernamePassword;
 use crate::{error::MyError, tokio_tools::run_in_tokio};
 
-#[derive(Deserialize, Debug, Clone)]
+/// Migrations embedded into the binary at compile time from the `migrations` folder
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct DbConfig {
     pub pool_size: u32,
     pub connection: UrlWithUsernamePassword,
@@ -55,22 +62,50 @@ pub struct PersistenceConfig {
 #[derive(Debug, Clone)]
 pub struct PersistenceState {
     config: PersistenceConfig,
-    pub pool_pg: PgPool,
+    /// Replaced when the pool is resized by a config reload
+    pool_pg: Arc<RwLock<PgPool>>,
+}
+
+async fn connect(config: &PersistenceConfig) -> Result<PgPool, MyError> {
+    Ok(PgPoolOptions::new()
+        .max_connections(config.db.pool_size)
+        .acquire_timeout(Duration::from_secs(config.db.acquire_timeout))
+        .connect(config.db.connection().as_str())
+        .await?)
 }
 
 impl PersistenceState {
     pub async fn new(config: &PersistenceConfig) -> Result<PersistenceState, MyError> {
-        let pool_pg = PgPoolOptions::new()
-            .max_connections(config.db.pool_size)
-            .acquire_timeout(Duration::from_secs(config.db.acquire_timeout))
-            .connect(config.db.connection().as_str())
-            .await?;
+        let pool_pg = connect(config).await?;
 
         Ok(PersistenceState {
             config: config.clone(),
-            pool_pg,
+            pool_pg: Arc::new(RwLock::new(pool_pg)),
         })
     }
+
+    /// The current pool, fetch it per use rather than holding on to it
+    pub fn pool(&self) -> PgPool {
+        self.pool_pg.read().unwrap().clone()
+    }
+
+    /// Swap in a pool of `pool_size` connections
+    ///
+    /// The old pool is closed in the background: requests already holding a connection
+    /// finish on it, new acquires from it fail.
+    pub async fn resize(&self, pool_size: u32) -> Result<(), MyError> {
+        let mut config = self.config.clone();
+        config.db.pool_size = pool_size;
+        let pool_pg = connect(&config).await?;
+
+        let old = std::mem::replace(&mut *self.pool_pg.write().unwrap(), pool_pg);
+        info!("Database pool resized to {pool_size} connections");
+        tokio::spawn(async move {
+            old.close().await;
+            debug!("Previous database pool closed");
+        });
+        Ok(())
+    }
 }
 
 pub async fn db_count_records(
@@ -79,7 +114,7 @@ pub async fn db_count_records(
 ) -> Result<(), MyError> {
     let state = PersistenceState::new(config).await?;
 
-    let pool_pg = state.pool_pg.clone();
+    let pool_pg = state.pool();
 
     let _select_reply = sqlx::query("SELECT 1").fetch_one(&pool_pg).await?;
 
@@ -113,14 +148,10 @@ pub fn start_db_check_tables(config: &PersistenceConfig) -> Result<(), MyError>
 pub async fn db_migrate(ct: CancellationToken, config: &PersistenceConfig) -> Result<(), MyError> {
     let state = PersistenceState::new(config).await?;
 
-    let pool = state.pool_pg.clone();
+    let pool = state.pool();
 
-    // Run migrations
-    // sqlx::migrate!() macro finds the migrations folder relative to Cargo.toml
-    // It embeds the migration files into the binary at compile time.
-    sqlx::migrate!() // Path relative to Cargo.toml
-        .run(&pool)
-        .await?;
+    // Run the migrations embedded by sqlx::migrate!() in MIGRATOR
+    MIGRATOR.run(&pool).await?;
 
     ct.cancel();
 
@@ -151,7 +182,7 @@ pub async fn db_backup(
 ) -> Result<(), MyError> {
     let state = PersistenceState::new(config).await?;
 
-    let pool = state.pool_pg.clone();
+    let pool = state.pool();
 
     let query = "SELECT * FROM users";
 
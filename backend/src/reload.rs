@@ -0,0 +1,382 @@
+//! Hot reload of the settings that are safe to change while running
+//!
+//! The config file and every file under the secrets directory are polled for changes, and
+//! SIGHUP forces a reload. A reload re-reads the config through `MyConfig::figment` and applies:
+//!
+//! - `webservice.forwarding_headers`
+//! - `logging`
+//! - `persistence.db.pool_size`, by swapping in a new pool
+//! - `risk`
+//!
+//! Changes to anything else are reported as needing a restart and are not applied.
+
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_with::{DurationSeconds, serde_as};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use crate::{
+    MyState,
+    config::{MyConfig, check::validate},
+    error::MyError,
+    logging::{self, LoggingConfig},
+    risk::RiskConfig,
+};
+
+#[serde_as]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ReloadConfig {
+    /// Poll the config file and secrets directory for changes
+    pub watch: bool,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub interval: Duration,
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        ReloadConfig {
+            watch: true,
+            interval: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Where the running config was loaded from
+#[derive(Debug, Clone)]
+pub struct ConfigFiles {
+    pub config: PathBuf,
+    pub secrets: PathBuf,
+}
+
+impl ConfigFiles {
+    pub fn load(&self) -> Result<MyConfig, MyError> {
+        let yaml = std::fs::read_to_string(&self.config)?;
+        Ok(MyConfig::figment(&yaml, &self.secrets).extract()?)
+    }
+
+    /// Modification time and length of the config file and each secret file
+    ///
+    /// Metadata follows symlinks so the atomic `..data` swap of mounted Kubernetes secrets
+    /// and config maps is seen as a change.
+    fn fingerprint(&self) -> BTreeMap<PathBuf, (SystemTime, u64)> {
+        let mut files = BTreeMap::new();
+        let mut pending = vec![self.config.clone(), self.secrets.clone()];
+
+        while let Some(path) = pending.pop() {
+            let Ok(metadata) = std::fs::metadata(&path) else {
+                continue;
+            };
+            if metadata.is_dir() {
+                if let Ok(entries) = std::fs::read_dir(&path) {
+                    pending.extend(entries.flatten().map(|entry| entry.path()));
+                }
+            } else {
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                files.insert(path, (modified, metadata.len()));
+            }
+        }
+        files
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ReloadReport {
+    pub reloaded_at: DateTime<Utc>,
+    /// Settings changed and applied
+    pub applied: Vec<String>,
+    /// Settings changed that only take effect after a restart
+    pub restart_required: Vec<String>,
+    /// Why the config or a setting could not be applied
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+/// The config currently in effect, as changed by reloads
+#[derive(Debug, Clone)]
+pub struct LiveConfig {
+    current: Arc<RwLock<MyConfig>>,
+}
+
+impl LiveConfig {
+    pub fn new(config: &MyConfig) -> Self {
+        LiveConfig {
+            current: Arc::new(RwLock::new(config.clone())),
+        }
+    }
+
+    pub fn forwarding_headers(&self) -> Vec<String> {
+        self.current
+            .read()
+            .unwrap()
+            .webservice
+            .forwarding_headers
+            .clone()
+    }
+
+    pub fn logging(&self) -> LoggingConfig {
+        self.current.read().unwrap().logging.clone()
+    }
+
+    pub fn risk(&self) -> RiskConfig {
+        self.current.read().unwrap().risk.clone()
+    }
+}
+
+/// Names of the settings differing between `old` and `new`, split into live and restart
+///
+/// `HamsConfig` comes from hamsrs without `PartialEq`, so it alone is compared through its
+/// `Debug` output.
+pub fn changes(old: &MyConfig, new: &MyConfig) -> (Vec<&'static str>, Vec<&'static str>) {
+    let mut live = vec![];
+    if old.webservice.forwarding_headers != new.webservice.forwarding_headers {
+        live.push("webservice.forwarding_headers");
+    }
+    if old.logging != new.logging {
+        live.push("logging");
+    }
+    if old.persistence.db.pool_size != new.persistence.db.pool_size {
+        live.push("persistence.db.pool_size");
+    }
+    if old.risk != new.risk {
+        live.push("risk");
+    }
+
+    let mut old_db = old.persistence.db.clone();
+    old_db.pool_size = new.persistence.db.pool_size;
+
+    let restart = [
+        (
+            "hams",
+            format!("{:?}", old.hams) != format!("{:?}", new.hams),
+        ),
+        ("checks", old.checks != new.checks),
+        ("runtime", old.runtime != new.runtime),
+        ("webservice.url", old.webservice.url != new.webservice.url),
+        (
+            "webservice.drain_timeout",
+            old.webservice.drain_timeout != new.webservice.drain_timeout,
+        ),
+        (
+            "webservice.openapi_ui",
+            old.webservice.openapi_ui != new.webservice.openapi_ui,
+        ),
+        ("persistence.db", old_db != new.persistence.db),
+        ("scraper", old.scraper != new.scraper),
+        ("reload", old.reload != new.reload),
+        ("telemetry", old.telemetry != new.telemetry),
+        ("graphql", old.graphql != new.graphql),
+    ]
+    .into_iter()
+    .filter_map(|(name, changed)| changed.then_some(name))
+    .collect();
+
+    (live, restart)
+}
+
+/// Re-read the config and apply the live settings that changed
+pub async fn reload(state: &MyState, files: &ConfigFiles) -> ReloadReport {
+    let mut report = ReloadReport {
+        reloaded_at: Utc::now(),
+        applied: vec![],
+        restart_required: vec![],
+        errors: vec![],
+    };
+
+    let new = match files.load() {
+        Ok(new) => new,
+        Err(err) => {
+            report.errors.push(format!("config not reloaded: {err}"));
+            return report;
+        }
+    };
+    let problems = validate(&new);
+    if !problems.is_empty() {
+        let problems = problems.join(", ");
+        report
+            .errors
+            .push(format!("config not reloaded: {problems}"));
+        return report;
+    }
+
+    let current = state.live.current.read().unwrap().clone();
+    let (live, restart) = changes(&current, &new);
+    report.restart_required = restart.into_iter().map(String::from).collect();
+
+    let mut applied = current;
+    for setting in live {
+        let result = match setting {
+            "webservice.forwarding_headers" => {
+                applied.webservice.forwarding_headers = new.webservice.forwarding_headers.clone();
+                Ok(())
+            }
+            "logging" => logging::apply(&new.logging).map(|_| {
+                applied.logging = new.logging.clone();
+            }),
+            "persistence.db.pool_size" => state
+                .db_state
+                .resize(new.persistence.db.pool_size)
+                .await
+                .map(|_| applied.persistence.db.pool_size = new.persistence.db.pool_size),
+            "risk" => {
+                applied.risk = new.risk.clone();
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        match result {
+            Ok(()) => report.applied.push(setting.into()),
+            Err(err) => report.errors.push(format!("{setting} not applied: {err}")),
+        }
+    }
+
+    *state.live.current.write().unwrap() = applied;
+    report
+}
+
+fn log_report(report: &ReloadReport) {
+    for error in &report.errors {
+        error!("Config reload: {error}");
+    }
+    if !report.applied.is_empty() {
+        info!("Config reload applied {}", report.applied.join(", "));
+    }
+    if !report.restart_required.is_empty() {
+        warn!(
+            "Config changes to {} need a restart to take effect",
+            report.restart_required.join(", ")
+        );
+    }
+}
+
+/// SIGHUP, never received where there are no unix signals
+struct Hangup {
+    #[cfg(unix)]
+    signal: tokio::signal::unix::Signal,
+}
+
+impl Hangup {
+    fn new() -> Result<Self, MyError> {
+        Ok(Hangup {
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?,
+        })
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        self.signal.recv().await;
+        #[cfg(not(unix))]
+        std::future::pending::<()>().await;
+    }
+}
+
+/// The next poll for file changes, never when not watching
+async fn tick(poll: &mut Option<tokio::time::Interval>) {
+    match poll {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending::<()>().await,
+    }
+}
+
+/// Reload on SIGHUP and, when watching, whenever the config or secret files change
+pub async fn reload_cancellable(
+    ct: CancellationToken,
+    state: MyState,
+    files: ConfigFiles,
+) -> Result<(), MyError> {
+    let config = state.config.reload.clone();
+    let mut hangup = Hangup::new()?;
+    let mut poll = config.watch.then(|| tokio::time::interval(config.interval));
+    let mut fingerprint = files.fingerprint();
+
+    if config.watch {
+        info!(
+            "Watching {} and {} for config changes",
+            files.config.display(),
+            files.secrets.display()
+        );
+    }
+
+    loop {
+        tokio::select! {
+            _ = ct.cancelled() => return Ok(()),
+            _ = hangup.recv() => info!("Received SIGHUP, reloading config"),
+            _ = tick(&mut poll) => {
+                let latest = files.fingerprint();
+                if latest == fingerprint {
+                    continue;
+                }
+                info!("Config files changed, reloading config");
+            }
+        }
+
+        fingerprint = files.fingerprint();
+        let report = reload(&state, &files).await;
+        log_report(&report);
+        state.health.set_reload(report);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn split_live_and_restart_changes() {
+        let old = MyConfig::default();
+        let mut new = old.clone();
+        assert_eq!(changes(&old, &new), (vec![], vec![]));
+
+        new.webservice.forwarding_headers = vec!["x-request-id".into()];
+        new.persistence.db.pool_size += 5;
+        new.logging.filter = Some("debug".into());
+        new.webservice.url = "http://0.0.0.0:9090/api".parse().unwrap();
+        new.scraper.enabled = true;
+        new.risk.weights.owner = 5.0;
+
+        let (live, restart) = changes(&old, &new);
+        assert_eq!(
+            live,
+            vec![
+                "webservice.forwarding_headers",
+                "logging",
+                "persistence.db.pool_size",
+                "risk"
+            ]
+        );
+        assert_eq!(restart, vec!["webservice.url", "scraper"]);
+    }
+
+    #[test]
+    fn fingerprint_sees_secret_changes() {
+        let dir = std::env::temp_dir().join(format!(
+            "fingerprint_sees_secret_changes-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(dir.join("db")).unwrap();
+        std::fs::write(dir.join("config.yaml"), "a: 1").unwrap();
+        std::fs::write(dir.join("db/password"), "one").unwrap();
+
+        let files = ConfigFiles {
+            config: dir.join("config.yaml"),
+            secrets: dir.join("db"),
+        };
+        let before = files.fingerprint();
+        assert_eq!(before.len(), 2);
+
+        std::fs::write(dir.join("db/password"), "longer").unwrap();
+        assert_ne!(files.fingerprint(), before);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
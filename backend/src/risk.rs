@@ -0,0 +1,314 @@
+//! Risk score of each entity from weighted factors
+//!
+//! Each factor is valued between 0 (no risk) and 1, and the score is the weighted mean of the
+//! values scaled to 0 to 100. Weights come from `risk.weights` in the config, a weight of 0
+//! leaves a factor out.
+//!
+//! - `fan_in`: entities depending on it directly, relative to the most depended on entity
+//! - `availability`: declared unavailability, 1 at or below `risk.availability_floor`
+//! - `latency`: declared `p99_millis` relative to `risk.latency_ceiling_millis`
+//! - `redundancy`: share of its consumers with no other dependency of its type
+//! - `owner`: 1 when no team owns it
+//!
+//! Undeclared availability and latency count as the highest risk.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{graph::Graph, webserver::entities::EntitySummary};
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct RiskWeights {
+    pub fan_in: f64,
+    pub availability: f64,
+    pub latency: f64,
+    pub redundancy: f64,
+    pub owner: f64,
+}
+
+impl Default for RiskWeights {
+    fn default() -> Self {
+        RiskWeights {
+            fan_in: 3.0,
+            availability: 2.0,
+            latency: 1.0,
+            redundancy: 3.0,
+            owner: 1.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct RiskConfig {
+    pub weights: RiskWeights,
+    /// Declared availability percentage at or below which `availability` is 1
+    pub availability_floor: f64,
+    /// Declared `p99_millis` at or above which `latency` is 1
+    pub latency_ceiling_millis: f64,
+}
+
+impl Default for RiskConfig {
+    fn default() -> Self {
+        RiskConfig {
+            weights: RiskWeights::default(),
+            availability_floor: 99.0,
+            latency_ceiling_millis: 1000.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Factor {
+    FanIn,
+    Availability,
+    Latency,
+    Redundancy,
+    Owner,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct FactorScore {
+    pub factor: Factor,
+    pub weight: f64,
+    /// Between 0 and 1
+    pub value: f64,
+    /// Points added to the score
+    pub contribution: f64,
+    pub explanation: String,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct EntityRisk {
+    #[serde(flatten)]
+    pub entity: EntitySummary,
+    /// Between 0 and 100
+    pub score: f64,
+    pub factors: Vec<FactorScore>,
+}
+
+impl EntityRisk {
+    /// Contribution of `factor` to the score
+    pub fn contribution(&self, factor: Factor) -> f64 {
+        self.factors
+            .iter()
+            .find(|score| score.factor == factor)
+            .map_or(0.0, |score| score.contribution)
+    }
+}
+
+/// Score every entity of `graph`, in graph order
+pub fn score(graph: &Graph, config: &RiskConfig) -> Vec<EntityRisk> {
+    let weights = &config.weights;
+    let total_weight = weights.fan_in
+        + weights.availability
+        + weights.latency
+        + weights.redundancy
+        + weights.owner;
+
+    let sole = graph.sole_dependency_of();
+    let fan_in: Vec<usize> = (0..graph.len())
+        .map(|node| {
+            let mut dependents: Vec<usize> =
+                graph.dependents(node).filter(|&d| d != node).collect();
+            dependents.sort_unstable();
+            dependents.dedup();
+            dependents.len()
+        })
+        .collect();
+    let most_fan_in = fan_in.iter().copied().max().unwrap_or(0);
+
+    graph
+        .entities
+        .iter()
+        .enumerate()
+        .map(|(node, entity)| {
+            let fan_in_value = if most_fan_in == 0 {
+                0.0
+            } else {
+                fan_in[node] as f64 / most_fan_in as f64
+            };
+
+            let allowed = (100.0 - config.availability_floor).max(f64::MIN_POSITIVE);
+            let availability_value = if entity.availability <= 0.0 {
+                1.0
+            } else {
+                ((100.0 - entity.availability) / allowed).clamp(0.0, 1.0)
+            };
+
+            let latency_value = if entity.p99_millis <= 0 {
+                1.0
+            } else {
+                (entity.p99_millis as f64 / config.latency_ceiling_millis).clamp(0.0, 1.0)
+            };
+
+            let redundancy_value = if fan_in[node] == 0 {
+                0.0
+            } else {
+                sole[node].len() as f64 / fan_in[node] as f64
+            };
+
+            let factors = [
+                (
+                    Factor::FanIn,
+                    weights.fan_in,
+                    fan_in_value,
+                    format!(
+                        "{} direct dependents, the most depended on entity has {most_fan_in}",
+                        fan_in[node]
+                    ),
+                ),
+                (
+                    Factor::Availability,
+                    weights.availability,
+                    availability_value,
+                    if entity.availability <= 0.0 {
+                        "no availability declared".to_string()
+                    } else {
+                        format!(
+                            "declared availability {}% against a floor of {}%",
+                            entity.availability, config.availability_floor
+                        )
+                    },
+                ),
+                (
+                    Factor::Latency,
+                    weights.latency,
+                    latency_value,
+                    if entity.p99_millis <= 0 {
+                        "no p99 latency declared".to_string()
+                    } else {
+                        format!(
+                            "declared p99 of {}ms against a ceiling of {}ms",
+                            entity.p99_millis, config.latency_ceiling_millis
+                        )
+                    },
+                ),
+                (
+                    Factor::Redundancy,
+                    weights.redundancy,
+                    redundancy_value,
+                    format!(
+                        "{} of {} consumers have no other {} to fall back on",
+                        sole[node].len(),
+                        fan_in[node],
+                        entity.entity_type
+                    ),
+                ),
+                (
+                    Factor::Owner,
+                    weights.owner,
+                    if entity.owner_team_id.is_none() {
+                        1.0
+                    } else {
+                        0.0
+                    },
+                    match entity.owner_team_id {
+                        Some(team) => format!("owned by team {team}"),
+                        None => "no owning team".to_string(),
+                    },
+                ),
+            ]
+            .into_iter()
+            .map(|(factor, weight, value, explanation)| FactorScore {
+                factor,
+                weight,
+                value,
+                contribution: if total_weight > 0.0 {
+                    100.0 * weight * value / total_weight
+                } else {
+                    0.0
+                },
+                explanation,
+            })
+            .collect::<Vec<_>>();
+
+            EntityRisk {
+                entity: EntitySummary::from(entity),
+                score: factors.iter().map(|factor| factor.contribution).sum(),
+                factors,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+    use crate::webserver::{entities::Entity, relationships::Relationship};
+
+    fn entity(id: i64, entity_type: &str, availability: f64, owner: Option<i64>) -> Entity {
+        serde_json::from_value(json!({
+            "id": id, "name": format!("e{id}"), "type": entity_type,
+            "p99_millis": 500, "p95_millis": 200, "availability": availability,
+            "throughput_rps": 0, "attributes": {}, "owner_team_id": owner,
+        }))
+        .unwrap()
+    }
+
+    fn relationship(from_id: i64, to_id: i64) -> Relationship {
+        serde_json::from_value(json!({
+            "id": from_id * 100 + to_id, "from_id": from_id, "to_id": to_id,
+            "relationship_type": "depends_on", "attributes": {},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn factors_are_weighted_and_explained() {
+        // two services share a database, the first also has a redundant pair of caches
+        let graph = Graph::new(
+            vec![
+                entity(1, "service", 99.9, Some(7)),
+                entity(2, "service", 99.9, Some(7)),
+                entity(3, "database", 99.5, None),
+                entity(4, "cache", 0.0, Some(7)),
+                entity(5, "cache", 99.9, Some(7)),
+            ],
+            vec![
+                relationship(1, 3),
+                relationship(2, 3),
+                relationship(1, 4),
+                relationship(1, 5),
+            ],
+        );
+        let risks = score(&graph, &RiskConfig::default());
+
+        // weights 3 + 2 + 1 + 3 + 1 = 10, so each weight point is worth 10
+        let database = &risks[2];
+        assert_eq!(database.contribution(Factor::FanIn), 30.0);
+        assert_eq!(database.contribution(Factor::Availability), 10.0);
+        assert_eq!(database.contribution(Factor::Latency), 5.0);
+        assert_eq!(database.contribution(Factor::Redundancy), 30.0);
+        assert_eq!(database.contribution(Factor::Owner), 10.0);
+        assert_eq!(database.score, 85.0);
+        assert_eq!(
+            database.factors[3].explanation,
+            "2 of 2 consumers have no other database to fall back on"
+        );
+
+        // the undeclared cache is backed up by its sibling
+        let cache = &risks[3];
+        assert_eq!(cache.contribution(Factor::FanIn), 15.0);
+        assert_eq!(cache.contribution(Factor::Availability), 20.0);
+        assert_eq!(cache.contribution(Factor::Redundancy), 0.0);
+        assert_eq!(cache.factors[1].explanation, "no availability declared");
+
+        let only_owner = RiskConfig {
+            weights: RiskWeights {
+                fan_in: 0.0,
+                availability: 0.0,
+                latency: 0.0,
+                redundancy: 0.0,
+                owner: 1.0,
+            },
+            ..RiskConfig::default()
+        };
+        let scores: Vec<f64> = score(&graph, &only_owner).iter().map(|r| r.score).collect();
+        assert_eq!(scores, vec![0.0, 0.0, 100.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,234 @@
+//! Parser for the Prometheus text exposition format and PromQL style series selectors
+//!
+//! Only instant samples are needed so `# HELP`/`# TYPE` metadata and timestamps are ignored.
+//! Selectors support the `=` and `!=` label matchers, e.g.
+//! `http_requests_total{job="api",code!="500"}`; every matching series is summed.
+
+use std::{collections::BTreeMap, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub name: String,
+    pub labels: BTreeMap<String, String>,
+    pub value: f64,
+}
+
+fn valid_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+/// A label matcher, `equal` is `true` for `=` and `false` for `!=`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matcher {
+    pub label: String,
+    pub equal: bool,
+    pub value: String,
+}
+
+/// Parse `{name="value",...}` returning the labels and the text after the closing brace
+fn parse_labels(text: &str) -> Result<(Vec<Matcher>, &str), String> {
+    let mut labels = vec![];
+    let mut rest = text
+        .strip_prefix('{')
+        .ok_or_else(|| "expected `{`".to_string())?;
+
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix('}') {
+            return Ok((labels, after));
+        }
+
+        let name_end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .ok_or_else(|| "unterminated label set".to_string())?;
+        let name = &rest[..name_end];
+        if name.is_empty() {
+            return Err(format!("expected label name at `{rest}`"));
+        }
+        rest = rest[name_end..].trim_start();
+
+        let equal = if let Some(after) = rest.strip_prefix("!=") {
+            rest = after;
+            false
+        } else if let Some(after) = rest.strip_prefix('=') {
+            rest = after;
+            true
+        } else {
+            return Err(format!("expected `=` or `!=` after label `{name}`"));
+        };
+
+        rest = rest
+            .trim_start()
+            .strip_prefix('"')
+            .ok_or_else(|| format!("expected quoted value for label `{name}`"))?;
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        let end = loop {
+            match chars.next() {
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, c)) => value.push(c),
+                    None => return Err("unterminated label value".into()),
+                },
+                Some((idx, '"')) => break idx,
+                Some((_, c)) => value.push(c),
+                None => return Err("unterminated label value".into()),
+            }
+        };
+        labels.push(Matcher {
+            label: name.to_owned(),
+            equal,
+            value,
+        });
+
+        rest = rest[end + 1..].trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest);
+    }
+}
+
+fn parse_value(text: &str) -> Result<f64, String> {
+    match text {
+        "+Inf" | "Inf" => Ok(f64::INFINITY),
+        "-Inf" => Ok(f64::NEG_INFINITY),
+        "NaN" => Ok(f64::NAN),
+        _ => text
+            .parse()
+            .map_err(|_| format!("invalid sample value `{text}`")),
+    }
+}
+
+/// Parse a text exposition document into its samples
+pub fn parse(text: &str) -> Result<Vec<Sample>, String> {
+    let mut samples = vec![];
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let context = |err: String| format!("line {}: {err}", number + 1);
+
+        let name_end = line
+            .find(|c: char| c == '{' || c.is_whitespace())
+            .ok_or_else(|| context("missing sample value".into()))?;
+        let name = &line[..name_end];
+        if !valid_name(name) {
+            return Err(context(format!("invalid metric name `{name}`")));
+        }
+
+        let (labels, rest) = if line[name_end..].starts_with('{') {
+            parse_labels(&line[name_end..]).map_err(context)?
+        } else {
+            (vec![], &line[name_end..])
+        };
+
+        let value = rest
+            .split_whitespace()
+            .next()
+            .ok_or_else(|| context("missing sample value".into()))?;
+
+        samples.push(Sample {
+            name: name.to_owned(),
+            labels: labels
+                .into_iter()
+                .map(|matcher| (matcher.label, matcher.value))
+                .collect(),
+            value: parse_value(value).map_err(context)?,
+        });
+    }
+
+    Ok(samples)
+}
+
+/// A metric name with optional label matchers
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    pub name: String,
+    pub matchers: Vec<Matcher>,
+}
+
+impl Selector {
+    pub fn matches(&self, sample: &Sample) -> bool {
+        sample.name == self.name
+            && self.matchers.iter().all(|matcher| {
+                let value = sample.labels.get(&matcher.label).map(String::as_str);
+                (value.unwrap_or("") == matcher.value) == matcher.equal
+            })
+    }
+
+    /// Sum every matching series, `None` when nothing matches
+    pub fn evaluate(&self, samples: &[Sample]) -> Option<f64> {
+        samples
+            .iter()
+            .filter(|sample| self.matches(sample) && !sample.value.is_nan())
+            .map(|sample| sample.value)
+            .reduce(|sum, value| sum + value)
+    }
+}
+
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let name_end = text.find('{').unwrap_or(text.len());
+        let name = text[..name_end].trim_end();
+        if !valid_name(name) {
+            return Err(format!("invalid metric name `{name}`"));
+        }
+
+        let matchers = if name_end < text.len() {
+            let (matchers, rest) = parse_labels(&text[name_end..])?;
+            if !rest.trim().is_empty() {
+                return Err(format!("unexpected `{}` after selector", rest.trim()));
+            }
+            matchers
+        } else {
+            vec![]
+        };
+
+        Ok(Selector {
+            name: name.to_owned(),
+            matchers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_exposition_and_select() {
+        let samples = parse(include_str!("../../test-data/prometheus/metrics.txt")).unwrap();
+        assert!(samples.iter().any(|s| s.name == "up" && s.value == 1.0));
+
+        let latency: Selector =
+            r#"http_request_duration_seconds{quantile="0.95"}"#.parse().unwrap();
+        assert_eq!(latency.evaluate(&samples), Some(0.082));
+
+        let errors: Selector = r#"http_requests_total{code!="200"}"#.parse().unwrap();
+        assert_eq!(errors.evaluate(&samples), Some(12.0));
+
+        let all: Selector = "http_requests_total".parse().unwrap();
+        assert_eq!(all.evaluate(&samples), Some(10012.0));
+
+        let missing: Selector = r#"http_requests_total{job="other"}"#.parse().unwrap();
+        assert_eq!(missing.evaluate(&samples), None);
+    }
+
+    #[test]
+    fn reject_malformed_input() {
+        assert!(parse("metric{label=unquoted} 1").is_err());
+        assert!(parse("metric").is_err());
+        assert!(parse("metric 1.2.3").is_err());
+        assert!("rate(metric[5m])".parse::<Selector>().is_err());
+        assert_eq!(
+            parse(r#"metric{path="a\"b"} NaN 1700000000"#).unwrap()[0].labels["path"],
+            "a\"b"
+        );
+    }
+}
@@ -0,0 +1,464 @@
+//! Pull observed SLIs from the Prometheus endpoints teams already expose
+//!
+//! An entity opts in with a `prometheus` object in its `attributes` naming the endpoint and
+//! a series selector for each SLI, optionally scaled into the unit the SLI is stored in:
+//!
+//! ```json
+//! {
+//!   "prometheus": {
+//!     "url": "http://payments:9090/metrics",
+//!     "throughput_rps": "http_requests_per_second{job=\"api\"}",
+//!     "p95_millis": {"metric": "http_request_duration_seconds{quantile=\"0.95\"}", "scale": 1000},
+//!     "p99_millis": {"metric": "http_request_duration_seconds{quantile=\"0.99\"}", "scale": 1000},
+//!     "availability": {"metric": "service_availability_ratio", "scale": 100}
+//!   }
+//! }
+//! ```
+//!
+//! Every scrape records an `sli_measurements` row per entity and, when `update_entities` is
+//! set, copies the values onto the entity's `throughput_rps`, latency and availability. Turn it
+//! off to keep those columns as declared objectives for error budgets and drift to compare
+//! against.
+//!
+//! The URLs come from entity attributes anyone able to edit an entity can set, so only
+//! `allowed_schemes` and hosts matching `allowed_hosts` are fetched and redirects are not
+//! followed. Targets are fetched concurrently and the whole scrape stops at `deadline`.
+
+pub mod exposition;
+
+use std::{collections::HashMap, time::Duration};
+
+use futures::{StreamExt, stream};
+
+use reqwest::{Client, redirect};
+use serde::{Deserialize, Serialize};
+use serde_with::{DurationSeconds, serde_as};
+use sqlx::PgPool;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+use url::Url;
+
+use crate::{
+    error::MyError,
+    forwarding::Forward,
+    persistence::PersistenceState,
+    slo::Measurement,
+    webserver::{DbBigSerial, slis::insert_measurements},
+};
+use exposition::{Sample, Selector};
+
+#[serde_as]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ScraperConfig {
+    /// Scrape in the background while the service runs
+    pub enabled: bool,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub interval: Duration,
+    /// Timeout for each request to a metrics endpoint
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub timeout: Duration,
+    /// Time allowed for scraping every target, those not answered by then fail
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub deadline: Duration,
+    /// Metrics endpoints fetched at the same time
+    pub concurrency: usize,
+    /// Copy scraped values onto the entity's `throughput_rps`, latency and availability
+    pub update_entities: bool,
+    /// URL schemes that may be scraped
+    pub allowed_schemes: Vec<String>,
+    /// Hosts that may be scraped: a name or address, `*.example.com` for its subdomains, or
+    /// `*` for any. Nothing is scraped when empty.
+    pub allowed_hosts: Vec<String>,
+}
+
+impl Default for ScraperConfig {
+    fn default() -> Self {
+        ScraperConfig {
+            enabled: false,
+            interval: Duration::from_secs(60),
+            timeout: Duration::from_secs(10),
+            deadline: Duration::from_secs(30),
+            concurrency: 8,
+            update_entities: true,
+            allowed_schemes: vec!["http".into(), "https".into()],
+            allowed_hosts: vec![],
+        }
+    }
+}
+
+impl ScraperConfig {
+    /// Refuse URLs outside the allowed schemes and hosts
+    pub fn permits(&self, url: &Url) -> Result<(), String> {
+        if !self
+            .allowed_schemes
+            .iter()
+            .any(|scheme| scheme == url.scheme())
+        {
+            return Err(format!(
+                "scheme {} of {url} is not in scraper.allowed_schemes",
+                url.scheme()
+            ));
+        }
+        let host = url.host_str().unwrap_or_default().to_lowercase();
+        let allowed = self.allowed_hosts.iter().any(|pattern| {
+            let pattern = pattern.to_lowercase();
+            match pattern.strip_prefix("*") {
+                Some("") => true,
+                Some(suffix) => suffix.starts_with('.') && host.ends_with(suffix),
+                None => host == pattern,
+            }
+        });
+        if !allowed {
+            return Err(format!("host of {url} is not in scraper.allowed_hosts"));
+        }
+        Ok(())
+    }
+}
+
+fn unit_scale() -> f64 {
+    1.0
+}
+
+/// A series selector, optionally multiplied by `scale` (e.g. `1000` for seconds to millis)
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum MetricMapping {
+    Selector(String),
+    Scaled {
+        metric: String,
+        #[serde(default = "unit_scale")]
+        scale: f64,
+    },
+}
+
+impl MetricMapping {
+    fn evaluate(&self, samples: &[Sample]) -> Result<Option<f64>, String> {
+        let (selector, scale) = match self {
+            MetricMapping::Selector(selector) => (selector, 1.0),
+            MetricMapping::Scaled { metric, scale } => (metric, *scale),
+        };
+        let selector: Selector = selector.parse()?;
+        Ok(selector.evaluate(samples).map(|value| value * scale))
+    }
+}
+
+/// The `prometheus` object of an entity's `attributes`
+#[derive(Deserialize, Debug, Clone)]
+pub struct PrometheusTarget {
+    pub url: Url,
+    #[serde(default)]
+    pub throughput_rps: Option<MetricMapping>,
+    #[serde(default)]
+    pub p95_millis: Option<MetricMapping>,
+    #[serde(default)]
+    pub p99_millis: Option<MetricMapping>,
+    #[serde(default)]
+    pub availability: Option<MetricMapping>,
+}
+
+impl PrometheusTarget {
+    /// Evaluate the mappings against scraped samples, unmapped or absent series are left unset
+    pub fn measure(&self, samples: &[Sample]) -> Result<Measurement, String> {
+        let evaluate = |mapping: &Option<MetricMapping>| match mapping {
+            Some(mapping) => mapping.evaluate(samples),
+            None => Ok(None),
+        };
+        let whole = |value: Option<f64>| value.map(|v| v.round() as i32);
+
+        let availability = evaluate(&self.availability)?;
+        if availability.is_some_and(|a| !(0.0..=100.0).contains(&a)) {
+            return Err(format!(
+                "availability {} is not a percentage, check its scale",
+                availability.unwrap()
+            ));
+        }
+
+        Ok(Measurement {
+            id: None,
+            entity_id: None,
+            measured_at: chrono::Utc::now(),
+            availability,
+            p95_millis: whole(evaluate(&self.p95_millis)?),
+            p99_millis: whole(evaluate(&self.p99_millis)?),
+            throughput_rps: whole(evaluate(&self.throughput_rps)?),
+            source: "prometheus".into(),
+        })
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ScrapeFailure {
+    pub entity_id: DbBigSerial,
+    pub message: String,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct ScrapeReport {
+    /// Entities with a `prometheus` target
+    pub targets: usize,
+    /// Entities that received a measurement
+    pub measured: Vec<DbBigSerial>,
+    pub failures: Vec<ScrapeFailure>,
+}
+
+/// Fetch and parse one text exposition endpoint
+pub async fn fetch(client: &Client, url: &Url) -> Result<Vec<Sample>, String> {
+    let body = client
+        .get(url.clone())
+        .forward_headers()
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| format!("scraping {url}: {err}"))?
+        .text()
+        .await
+        .map_err(|err| format!("reading {url}: {err}"))?;
+
+    exposition::parse(&body).map_err(|err| format!("parsing {url}: {err}"))
+}
+
+/// A client that does not follow redirects, so the allow-list cannot be sidestepped
+pub fn client(config: &ScraperConfig) -> Result<Client, MyError> {
+    Ok(Client::builder()
+        .timeout(config.timeout)
+        .redirect(redirect::Policy::none())
+        .build()?)
+}
+
+/// Scrape every entity with a `prometheus` target once
+///
+/// Each endpoint is fetched once however many entities share it, up to `concurrency` at a
+/// time, and endpoints not answered within `deadline` fail. Failures are reported per entity
+/// and do not stop the others being measured.
+pub async fn scrape(
+    pool: &PgPool,
+    client: &Client,
+    config: &ScraperConfig,
+) -> Result<ScrapeReport, MyError> {
+    let rows = sqlx::query_as::<_, (DbBigSerial, serde_json::Value)>(
+        "SELECT id, attributes -> 'prometheus' FROM entities WHERE attributes ? 'prometheus' ORDER BY id",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut report = ScrapeReport {
+        targets: rows.len(),
+        ..Default::default()
+    };
+
+    let mut targets = vec![];
+    for (entity_id, target) in rows {
+        let target = serde_json::from_value::<PrometheusTarget>(target)
+            .map_err(|err| format!("invalid prometheus attributes: {err}"))
+            .and_then(|target| config.permits(&target.url).map(|_| target));
+        match target {
+            Ok(target) => targets.push((entity_id, target)),
+            Err(message) => report.failures.push(ScrapeFailure { entity_id, message }),
+        }
+    }
+
+    let mut urls: Vec<Url> = targets
+        .iter()
+        .map(|(_, target)| target.url.clone())
+        .collect();
+    urls.sort();
+    urls.dedup();
+
+    let deadline = tokio::time::Instant::now() + config.deadline;
+    let fetches = urls.into_iter().map(|url| {
+        let client = client.clone();
+        async move {
+            let samples = tokio::time::timeout_at(deadline, fetch(&client, &url))
+                .await
+                .unwrap_or_else(|_| Err(format!("scraping {url}: scrape deadline exceeded")));
+            (url, samples)
+        }
+    });
+    let scraped: HashMap<Url, Result<Vec<Sample>, String>> = stream::iter(fetches)
+        .buffer_unordered(config.concurrency.max(1))
+        .collect()
+        .await;
+
+    let mut measurements = vec![];
+    for (entity_id, target) in &targets {
+        let measured = scraped[&target.url]
+            .as_ref()
+            .map_err(Clone::clone)
+            .and_then(|samples| target.measure(samples));
+        match measured {
+            Ok(measurement) if measurement.is_empty() => report.failures.push(ScrapeFailure {
+                entity_id: *entity_id,
+                message: "no mapped series found".into(),
+            }),
+            Ok(mut measurement) => {
+                measurement.entity_id = Some(*entity_id);
+                measurements.push(measurement);
+            }
+            Err(message) => report.failures.push(ScrapeFailure {
+                entity_id: *entity_id,
+                message,
+            }),
+        }
+    }
+
+    if measurements.is_empty() {
+        return Ok(report);
+    }
+
+    insert_measurements(pool, &measurements).await?;
+    if config.update_entities {
+        update_entities(pool, &measurements).await?;
+    }
+
+    report.measured = measurements.iter().filter_map(|m| m.entity_id).collect();
+    Ok(report)
+}
+
+/// Overwrite the SLI columns of each measured entity with the values that were scraped
+async fn update_entities(pool: &PgPool, measurements: &[Measurement]) -> Result<(), MyError> {
+    sqlx::query(
+        r#"
+        UPDATE entities
+        SET availability = COALESCE(scraped.availability, entities.availability),
+            p95_millis = COALESCE(scraped.p95_millis, entities.p95_millis),
+            p99_millis = COALESCE(scraped.p99_millis, entities.p99_millis),
+            throughput_rps = COALESCE(scraped.throughput_rps, entities.throughput_rps)
+        FROM UNNEST($1::BIGINT[], $2::FLOAT8[], $3::INTEGER[], $4::INTEGER[], $5::INTEGER[])
+            AS scraped(id, availability, p95_millis, p99_millis, throughput_rps)
+        WHERE entities.id = scraped.id
+        "#,
+    )
+    .bind(measurements.iter().map(|m| m.entity_id).collect::<Vec<_>>())
+    .bind(
+        measurements
+            .iter()
+            .map(|m| m.availability)
+            .collect::<Vec<_>>(),
+    )
+    .bind(
+        measurements
+            .iter()
+            .map(|m| m.p95_millis)
+            .collect::<Vec<_>>(),
+    )
+    .bind(
+        measurements
+            .iter()
+            .map(|m| m.p99_millis)
+            .collect::<Vec<_>>(),
+    )
+    .bind(
+        measurements
+            .iter()
+            .map(|m| m.throughput_rps)
+            .collect::<Vec<_>>(),
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Scrape on every `interval` until cancelled
+pub async fn scrape_cancellable(
+    ct: CancellationToken,
+    db: PersistenceState,
+    config: ScraperConfig,
+) -> Result<(), MyError> {
+    let client = client(&config)?;
+    let mut interval = tokio::time::interval(config.interval);
+
+    info!("Scraping Prometheus targets every {:?}", config.interval);
+
+    loop {
+        tokio::select! {
+            _ = ct.cancelled() => return Ok(()),
+            _ = interval.tick() => match scrape(&db.pool(), &client, &config).await {
+                Ok(report) => {
+                    info!(
+                        "Scraped {} of {} Prometheus targets",
+                        report.measured.len(),
+                        report.targets
+                    );
+                    for failure in report.failures {
+                        warn!("Scrape of entity {} failed: {}", failure.entity_id, failure.message);
+                    }
+                }
+                Err(err) => error!("Scrape failed: {err}"),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{Router, routing::get};
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn measure_from_static_file_server() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let app = Router::new().route(
+            "/metrics",
+            get(|| async { include_str!("../../test-data/prometheus/metrics.txt") }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let target: PrometheusTarget = serde_json::from_value(json!({
+            "url": format!("http://{address}/metrics"),
+            "throughput_rps": "http_requests_per_second{job=\"api\"}",
+            "p95_millis": {"metric": "http_request_duration_seconds{quantile=\"0.95\"}", "scale": 1000},
+            "p99_millis": {"metric": "http_request_duration_seconds{quantile=\"0.99\"}", "scale": 1000},
+            "availability": {"metric": "service_availability_ratio", "scale": 100}
+        }))
+        .unwrap();
+
+        let client = client(&ScraperConfig::default()).unwrap();
+        let samples = fetch(&client, &target.url).await.unwrap();
+        let measurement = target.measure(&samples).unwrap();
+
+        assert_eq!(measurement.throughput_rps, Some(42));
+        assert_eq!(measurement.p95_millis, Some(82));
+        assert_eq!(measurement.p99_millis, Some(211));
+        assert!((measurement.availability.unwrap() - 99.88).abs() < 1e-9);
+        assert_eq!(measurement.source, "prometheus");
+
+        let missing = Url::parse(&format!("http://{address}/missing")).unwrap();
+        assert!(fetch(&client, &missing).await.is_err());
+    }
+
+    #[test]
+    fn only_allowed_urls_are_scraped() {
+        let config = ScraperConfig {
+            allowed_hosts: vec!["payments".into(), "*.metrics.internal".into()],
+            ..ScraperConfig::default()
+        };
+        let permits = |url: &str| config.permits(&Url::parse(url).unwrap()).is_ok();
+
+        assert!(permits("http://payments:9090/metrics"));
+        assert!(permits("https://api.metrics.internal/metrics"));
+        assert!(!permits("http://metrics.internal.evil.com/metrics"));
+        assert!(!permits("http://169.254.169.254/latest/meta-data"));
+        assert!(!permits("file:///etc/passwd"));
+        let nothing = ScraperConfig::default();
+        assert!(
+            nothing
+                .permits(&Url::parse("http://payments/").unwrap())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn availability_must_be_a_percentage() {
+        let target: PrometheusTarget = serde_json::from_value(json!({
+            "url": "http://localhost/metrics",
+            "availability": "service_availability_ratio"
+        }))
+        .unwrap();
+        let samples = exposition::parse("service_availability_ratio 120").unwrap();
+        assert!(target.measure(&samples).is_err());
+    }
+}
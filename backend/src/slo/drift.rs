@@ -0,0 +1,273 @@
+//! Drift between the SLIs an entity declares and the SLIs it is observed to deliver
+//!
+//! Each metric is reduced to a shortfall, how many times worse than declared a sample is:
+//!
+//! - `availability`: observed unavailability over declared unavailability (the burn rate)
+//! - `p95_millis`, `p99_millis`: observed latency over declared latency
+//! - `throughput_rps`: declared throughput over observed throughput
+//!
+//! A shortfall above `1.0` breaches the declaration. A metric is `breaching` when at least
+//! `breach_ratio` of its samples breach and `over_promising` when its mean shortfall reaches
+//! `factor`, i.e. the declaration is wildly better than reality.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::MyError,
+    slo::{Measurement, Objective},
+};
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct DriftThresholds {
+    /// Fraction of breaching samples that makes a metric consistently breaching, above 0 and
+    /// at most 1
+    pub breach_ratio: f64,
+    /// Mean shortfall that makes a metric massively over-promised, above 1
+    pub factor: f64,
+}
+
+impl Default for DriftThresholds {
+    fn default() -> Self {
+        DriftThresholds {
+            breach_ratio: 0.5,
+            factor: 2.0,
+        }
+    }
+}
+
+impl DriftThresholds {
+    /// Reject thresholds that would flag every entity or none
+    pub fn validate(&self) -> Result<(), MyError> {
+        if !(self.breach_ratio > 0.0 && self.breach_ratio <= 1.0) {
+            return Err(MyError::Validation(
+                "breach_ratio must be greater than 0 and at most 1".into(),
+            ));
+        }
+        if !(self.factor > 1.0 && self.factor.is_finite()) {
+            return Err(MyError::Validation(
+                "factor must be a finite number greater than 1".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Ordered from best to worst so the worst metric decides an entity's status
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum DriftStatus {
+    /// Nothing declared or nothing observed to compare
+    NoData,
+    Met,
+    Breaching,
+    OverPromising,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct MetricDrift {
+    pub metric: &'static str,
+    pub declared: f64,
+    /// Mean of the observed samples
+    pub observed: f64,
+    pub samples: usize,
+    /// Fraction of samples worse than declared
+    pub breach_ratio: f64,
+    /// Mean of the per sample shortfalls, above `1.0` is worse than declared
+    pub shortfall: f64,
+    pub status: DriftStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EntityDrift {
+    pub status: DriftStatus,
+    /// Largest metric shortfall, used to rank entities of the same status
+    pub worst_shortfall: f64,
+    pub metrics: Vec<MetricDrift>,
+}
+
+fn metric_drift(
+    metric: &'static str,
+    declared: f64,
+    observed: Vec<f64>,
+    shortfall: impl Fn(f64) -> Option<f64>,
+    thresholds: &DriftThresholds,
+) -> Option<MetricDrift> {
+    if declared <= 0.0 || observed.is_empty() {
+        return None;
+    }
+
+    let shortfalls: Vec<f64> = observed.iter().filter_map(|&v| shortfall(v)).collect();
+    if shortfalls.is_empty() {
+        return None;
+    }
+
+    let samples = shortfalls.len();
+    let breach_ratio = shortfalls.iter().filter(|&&s| s > 1.0).count() as f64 / samples as f64;
+    let shortfall = shortfalls.iter().sum::<f64>() / samples as f64;
+
+    let status = if shortfall >= thresholds.factor {
+        DriftStatus::OverPromising
+    } else if breach_ratio >= thresholds.breach_ratio {
+        DriftStatus::Breaching
+    } else {
+        DriftStatus::Met
+    };
+
+    Some(MetricDrift {
+        metric,
+        declared,
+        observed: observed.iter().sum::<f64>() / observed.len() as f64,
+        samples,
+        breach_ratio,
+        shortfall,
+        status,
+    })
+}
+
+/// Compare an entity's declared objective against its measurements
+pub fn drift(
+    declared: &Objective,
+    measurements: &[Measurement],
+    thresholds: &DriftThresholds,
+) -> EntityDrift {
+    let observed = |value: fn(&Measurement) -> Option<f64>| -> Vec<f64> {
+        measurements.iter().filter_map(value).collect()
+    };
+
+    let unavailability = 100.0 - declared.availability;
+    let latency = |declared: i32| move |v: f64| Some(v / declared as f64);
+
+    let metrics: Vec<MetricDrift> = [
+        metric_drift(
+            "availability",
+            if unavailability > 0.0 {
+                declared.availability
+            } else {
+                0.0
+            },
+            observed(|m| m.availability),
+            |v| Some((100.0 - v).max(0.0) / unavailability),
+            thresholds,
+        ),
+        metric_drift(
+            "p95_millis",
+            declared.p95_millis as f64,
+            observed(|m| m.p95_millis.map(f64::from)),
+            latency(declared.p95_millis),
+            thresholds,
+        ),
+        metric_drift(
+            "p99_millis",
+            declared.p99_millis as f64,
+            observed(|m| m.p99_millis.map(f64::from)),
+            latency(declared.p99_millis),
+            thresholds,
+        ),
+        metric_drift(
+            "throughput_rps",
+            declared.throughput_rps as f64,
+            observed(|m| m.throughput_rps.map(f64::from)),
+            |v| (v > 0.0).then(|| declared.throughput_rps as f64 / v),
+            thresholds,
+        ),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    EntityDrift {
+        status: metrics
+            .iter()
+            .map(|m| m.status)
+            .max()
+            .unwrap_or(DriftStatus::NoData),
+        worst_shortfall: metrics.iter().map(|m| m.shortfall).fold(0.0, f64::max),
+        metrics,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn measured(availability: f64, p95: i32, throughput: i32) -> Measurement {
+        Measurement {
+            id: None,
+            entity_id: Some(1),
+            measured_at: Utc::now(),
+            availability: Some(availability),
+            p95_millis: Some(p95),
+            p99_millis: None,
+            throughput_rps: Some(throughput),
+            source: "manual".into(),
+        }
+    }
+
+    #[test]
+    fn classify_breaches_and_over_promises() {
+        let declared = Objective {
+            availability: 99.9,
+            p95_millis: 100,
+            p99_millis: 200,
+            throughput_rps: 50,
+        };
+        let thresholds = DriftThresholds::default();
+
+        let healthy = drift(
+            &declared,
+            &[measured(99.95, 90, 60), measured(99.92, 95, 55)],
+            &thresholds,
+        );
+        assert_eq!(healthy.status, DriftStatus::Met);
+        assert_eq!(healthy.metrics.len(), 3);
+
+        let slow = drift(
+            &declared,
+            &[measured(99.95, 110, 60), measured(99.95, 120, 60)],
+            &thresholds,
+        );
+        assert_eq!(slow.status, DriftStatus::Breaching);
+        assert_eq!(slow.metrics[1].breach_ratio, 1.0);
+
+        let flaky = drift(
+            &declared,
+            &[measured(99.0, 90, 60), measured(99.5, 90, 60)],
+            &thresholds,
+        );
+        assert_eq!(flaky.status, DriftStatus::OverPromising);
+        assert!((flaky.worst_shortfall - 7.5).abs() < 1e-6);
+
+        let nothing_declared = Objective {
+            availability: 0.0,
+            p95_millis: 0,
+            p99_millis: 0,
+            throughput_rps: 0,
+        };
+        assert_eq!(
+            drift(&nothing_declared, &[measured(99.0, 90, 60)], &thresholds).status,
+            DriftStatus::NoData
+        );
+    }
+
+    #[test]
+    fn thresholds_are_bounded() {
+        assert!(DriftThresholds::default().validate().is_ok());
+        for (breach_ratio, factor) in [
+            (0.0, 2.0),
+            (1.5, 2.0),
+            (f64::NAN, 2.0),
+            (0.5, 1.0),
+            (0.5, -2.0),
+            (0.5, f64::INFINITY),
+        ] {
+            let thresholds = DriftThresholds {
+                breach_ratio,
+                factor,
+            };
+            assert!(matches!(thresholds.validate(), Err(MyError::Validation(_))));
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Error budgets computed from measured SLIs against an entity's declared SLO
+//!
+//! Availability is a percentage (`99.9`) so an SLO of `99.9` allows `0.1%` of the window
+//! to be unavailable. The burn rate is the observed unavailability divided by that
+//! allowance: `1.0` uses the budget exactly over the window, `2.0` exhausts it halfway.
+
+pub mod drift;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::webserver::DbBigSerial;
+
+/// Rolling windows, in days, error budgets are reported over
+pub const WINDOW_DAYS: [i64; 3] = [7, 28, 30];
+
+/// Declared objectives of an entity, taken from its SLI columns, zero when not declared
+#[derive(Debug, Clone, Copy, Serialize, sqlx::FromRow)]
+pub struct Objective {
+    pub availability: f64,
+    pub p95_millis: i32,
+    pub p99_millis: i32,
+    pub throughput_rps: i32,
+}
+
+/// A measurement of one or more SLIs at a point in time
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Measurement {
+    #[serde(default)]
+    pub id: Option<DbBigSerial>,
+    #[serde(default)]
+    pub entity_id: Option<DbBigSerial>,
+    #[serde(default = "Utc::now")]
+    pub measured_at: DateTime<Utc>,
+    #[serde(default)]
+    pub availability: Option<f64>,
+    #[serde(default)]
+    pub p95_millis: Option<i32>,
+    #[serde(default)]
+    pub p99_millis: Option<i32>,
+    #[serde(default)]
+    pub throughput_rps: Option<i32>,
+    /// Where the measurement came from, e.g. `manual` or `prometheus`
+    #[serde(default = "default_source")]
+    pub source: String,
+}
+
+fn default_source() -> String {
+    "manual".into()
+}
+
+impl Measurement {
+    pub fn is_empty(&self) -> bool {
+        self.availability.is_none()
+            && self.p95_millis.is_none()
+            && self.p99_millis.is_none()
+            && self.throughput_rps.is_none()
+    }
+}
+
+/// Error budget over one rolling window
+#[derive(Debug, Serialize, PartialEq)]
+pub struct WindowBudget {
+    pub window_days: i64,
+    /// Measurements falling inside the window
+    pub samples: usize,
+    /// Mean of the measured availability, unset without availability samples
+    pub observed_availability: Option<f64>,
+    /// Observed unavailability over the allowed unavailability
+    pub burn_rate: Option<f64>,
+    /// Fraction of the window's budget left, negative once overspent
+    pub budget_remaining: Option<f64>,
+    /// Minutes of unavailability the SLO allows over the window
+    pub budget_minutes: f64,
+    /// Allowed minutes of unavailability not yet spent
+    pub budget_remaining_minutes: Option<f64>,
+    /// Fraction of latency samples meeting the declared `p95_millis`
+    pub p95_compliance: Option<f64>,
+    /// Fraction of latency samples meeting the declared `p99_millis`
+    pub p99_compliance: Option<f64>,
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0usize), |(sum, count), v| (sum + v, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+/// Compute the error budget over the `window_days` before `now`
+///
+/// Samples are treated as evenly spaced so the observed availability is their plain mean.
+/// Without a declared availability below 100% there is no budget to burn and the burn
+/// rate is left unset.
+pub fn window_budget(
+    objective: &Objective,
+    measurements: &[Measurement],
+    now: DateTime<Utc>,
+    window_days: i64,
+) -> WindowBudget {
+    let start = now - Duration::days(window_days);
+    let in_window: Vec<&Measurement> = measurements
+        .iter()
+        .filter(|m| m.measured_at > start && m.measured_at <= now)
+        .collect();
+
+    let observed_availability = mean(in_window.iter().filter_map(|m| m.availability));
+
+    let allowed = 100.0 - objective.availability;
+    let has_budget = objective.availability > 0.0 && allowed > 0.0;
+    let budget_minutes = if has_budget {
+        allowed / 100.0 * (window_days * 24 * 60) as f64
+    } else {
+        0.0
+    };
+
+    let burn_rate = observed_availability
+        .filter(|_| has_budget)
+        .map(|observed| (100.0 - observed).max(0.0) / allowed);
+    let budget_remaining = burn_rate.map(|burn| 1.0 - burn);
+    let budget_remaining_minutes = budget_remaining.map(|remaining| remaining * budget_minutes);
+
+    let compliance = |objective: i32, value: fn(&Measurement) -> Option<i32>| {
+        mean(
+            in_window
+                .iter()
+                .filter_map(|m| value(m))
+                .map(|v| if v <= objective { 1.0 } else { 0.0 }),
+        )
+        .filter(|_| objective > 0)
+    };
+
+    WindowBudget {
+        window_days,
+        samples: in_window.len(),
+        observed_availability,
+        burn_rate,
+        budget_remaining,
+        budget_minutes,
+        budget_remaining_minutes,
+        p95_compliance: compliance(objective.p95_millis, |m| m.p95_millis),
+        p99_compliance: compliance(objective.p99_millis, |m| m.p99_millis),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample(now: DateTime<Utc>, days_ago: i64, availability: f64, p95: i32) -> Measurement {
+        Measurement {
+            id: None,
+            entity_id: Some(1),
+            measured_at: now - Duration::days(days_ago),
+            availability: Some(availability),
+            p95_millis: Some(p95),
+            p99_millis: None,
+            throughput_rps: None,
+            source: "manual".into(),
+        }
+    }
+
+    #[test]
+    fn burn_rate_against_declared_availability() {
+        let now = Utc::now();
+        let objective = Objective {
+            availability: 99.9,
+            p95_millis: 100,
+            p99_millis: 0,
+            throughput_rps: 0,
+        };
+        let measurements = vec![
+            sample(now, 1, 99.95, 80),
+            sample(now, 2, 99.85, 120),
+            sample(now, 20, 99.0, 90),
+        ];
+
+        let week = window_budget(&objective, &measurements, now, 7);
+        assert_eq!(week.samples, 2);
+        assert!((week.observed_availability.unwrap() - 99.9).abs() < 1e-9);
+        assert!((week.burn_rate.unwrap() - 1.0).abs() < 1e-6);
+        assert!((week.budget_minutes - 10.08).abs() < 1e-6);
+        assert_eq!(week.p95_compliance, Some(0.5));
+        assert_eq!(week.p99_compliance, None);
+
+        let month = window_budget(&objective, &measurements, now, 28);
+        assert_eq!(month.samples, 3);
+        assert!(month.burn_rate.unwrap() > 1.0);
+        assert!(month.budget_remaining.unwrap() < 0.0);
+    }
+
+    #[test]
+    fn no_budget_without_declared_availability() {
+        let now = Utc::now();
+        let objective = Objective {
+            availability: 0.0,
+            p95_millis: 0,
+            p99_millis: 0,
+            throughput_rps: 0,
+        };
+        let budget = window_budget(&objective, &[sample(now, 1, 99.0, 10)], now, 30);
+        assert_eq!(budget.burn_rate, None);
+        assert_eq!(budget.budget_minutes, 0.0);
+        assert_eq!(budget.observed_availability, Some(99.0));
+    }
+}
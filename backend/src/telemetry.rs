@@ -0,0 +1,306 @@
+//! OpenTelemetry trace export
+//!
+//! Spans from `tracing` are bridged to OpenTelemetry by a layer installed with the logging
+//! setup, so HTTP request spans from the `TraceLayer` and the `sqlx::query` events recorded
+//! within them are exported. The layer looks up the global tracer provider for each span, so
+//! nothing is exported until [`install`] replaces the default no-op provider from config.
+//!
+//! Spans are posted as OTLP JSON to an OTLP/HTTP collector (`{endpoint}/v1/traces`) by the
+//! `opentelemetry-otlp` exporter, or appended one batch per line of OTLP JSON to a file for
+//! offline use, serialized with the `opentelemetry-proto` messages the collector accepts.
+//! Context is propagated with W3C `traceparent`/`tracestate` headers: extracted from inbound
+//! requests and injected into outbound calls by `forwarding::Forward`.
+
+use std::{fs::OpenOptions, io::Write, path::PathBuf, sync::OnceLock, time::Duration};
+
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use opentelemetry::{
+    Context, KeyValue, global,
+    propagation::{Extractor, Injector},
+    trace::{SpanBuilder, Tracer},
+};
+use opentelemetry_otlp::{Protocol, WithExportConfig};
+use opentelemetry_proto::{
+    tonic::collector::trace::v1::ExportTraceServiceRequest,
+    transform::trace::tonic::group_spans_by_resource_and_scope,
+};
+use opentelemetry_sdk::{
+    Resource,
+    error::{OTelSdkError, OTelSdkResult},
+    propagation::TraceContextPropagator,
+    trace::{SdkTracerProvider, SpanData, SpanExporter},
+};
+use serde::Deserialize;
+use serde_with::{DurationSeconds, serde_as};
+use tracing::{Span, Subscriber, error, info};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{Layer, registry::LookupSpan};
+use url::Url;
+
+use crate::{NAME, VERSION, error::MyError};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceExporter {
+    #[default]
+    None,
+    /// OTLP/HTTP with JSON encoding
+    Otlp,
+    /// OTLP JSON lines appended to `path`
+    File,
+}
+
+#[serde_as]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    pub exporter: TraceExporter,
+    /// Collector base URL, spans are posted to `{endpoint}/v1/traces`
+    pub endpoint: Url,
+    pub path: PathBuf,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub timeout: Duration,
+    /// Which spans and events are exported, as `EnvFilter` directives
+    pub filter: String,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        TelemetryConfig {
+            exporter: TraceExporter::None,
+            endpoint: Url::parse("http://localhost:4318").unwrap(),
+            path: PathBuf::from("traces.jsonl"),
+            timeout: Duration::from_secs(10),
+            filter: "info,sqlx::query=debug".into(),
+        }
+    }
+}
+
+/// Delegates to the global tracer provider at the time each span starts
+#[derive(Debug, Clone, Copy)]
+pub struct GlobalTracer;
+
+impl Tracer for GlobalTracer {
+    type Span = global::BoxedSpan;
+
+    fn build_with_context(&self, builder: SpanBuilder, parent_cx: &Context) -> Self::Span {
+        global::tracer(NAME).build_with_context(builder, parent_cx)
+    }
+}
+
+/// The `tracing` layer bridging spans to OpenTelemetry
+pub fn layer<S>() -> impl Layer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    tracing_opentelemetry::layer().with_tracer(GlobalTracer)
+}
+
+static PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+
+/// A provider describing this service, exporting spans in batches
+fn provider(exporter: impl SpanExporter + 'static) -> SdkTracerProvider {
+    SdkTracerProvider::builder()
+        .with_resource(
+            Resource::builder()
+                .with_service_name(NAME)
+                .with_attribute(KeyValue::new("service.version", VERSION))
+                .build(),
+        )
+        .with_batch_exporter(exporter)
+        .build()
+}
+
+/// `{endpoint}/v1/traces`, keeping any path the endpoint already has
+fn traces_url(endpoint: &Url) -> Result<Url, MyError> {
+    let mut url = endpoint.clone();
+    url.path_segments_mut()
+        .map_err(|_| MyError::Validation(format!("telemetry.endpoint: {endpoint} has no path")))?
+        .pop_if_empty()
+        .extend(["v1", "traces"]);
+    Ok(url)
+}
+
+/// Start exporting spans as configured, call outside the tokio runtime
+pub fn install(config: &TelemetryConfig) -> Result<(), MyError> {
+    let provider = match config.exporter {
+        TraceExporter::None => return Ok(()),
+        TraceExporter::Otlp => {
+            let url = traces_url(&config.endpoint)?;
+            info!("Exporting traces to {url}");
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_http()
+                .with_protocol(Protocol::HttpJson)
+                .with_endpoint(url.as_str())
+                .with_timeout(config.timeout)
+                .build()
+                .map_err(|err| MyError::Validation(format!("telemetry: {err}")))?;
+            provider(exporter)
+        }
+        TraceExporter::File => {
+            info!("Writing traces to {}", config.path.display());
+            provider(FileExporter {
+                path: config.path.clone(),
+                resource: Resource::builder_empty().build(),
+            })
+        }
+    };
+
+    global::set_tracer_provider(provider.clone());
+    PROVIDER.set(provider).ok();
+
+    crate::logging::apply_telemetry_filter(&config.filter)
+}
+
+/// Flush and stop exporting, call outside the tokio runtime
+pub fn shutdown() {
+    if let Some(provider) = PROVIDER.get()
+        && let Err(err) = provider.shutdown()
+    {
+        error!("Trace export shutdown failed: {err}");
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+/// Continue the trace of an inbound request in `span`
+pub fn set_parent(span: &Span, headers: &HeaderMap) {
+    let parent =
+        global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)));
+    span.set_parent(parent).ok();
+}
+
+/// Add the trace context of the current span to outbound headers
+pub fn inject(headers: &mut HeaderMap) {
+    let context = Span::current().context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(headers))
+    });
+}
+
+/// Appends each batch to `path` as a line of OTLP JSON
+#[derive(Debug)]
+struct FileExporter {
+    path: PathBuf,
+    resource: Resource,
+}
+
+impl SpanExporter for FileExporter {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        let request = ExportTraceServiceRequest {
+            resource_spans: group_spans_by_resource_and_scope(batch, &(&self.resource).into()),
+        };
+        let failed = |err: String| OTelSdkError::InternalFailure(err);
+
+        let line = serde_json::to_string(&request).map_err(|err| failed(err.to_string()))?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{line}"))
+            .map_err(|err| failed(err.to_string()))
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.resource = resource.clone();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use opentelemetry::trace::{Span as _, SpanKind, TracerProvider};
+    use serde_json::Value as Json;
+
+    use super::*;
+
+    #[test]
+    fn file_export_writes_otlp_json() {
+        let path = std::env::temp_dir().join(format!(
+            "file_export_writes_otlp_json-{}.jsonl",
+            std::process::id()
+        ));
+
+        let provider = SdkTracerProvider::builder()
+            .with_resource(Resource::builder().with_service_name(NAME).build())
+            .with_simple_exporter(FileExporter {
+                path: path.clone(),
+                resource: Resource::builder_empty().build(),
+            })
+            .build();
+        let mut span = provider
+            .tracer("test")
+            .span_builder("GET /entities")
+            .with_kind(SpanKind::Server)
+            .with_attributes([KeyValue::new("http.status", 200)])
+            .start(&provider.tracer("test"));
+        span.end();
+        provider.shutdown().unwrap();
+
+        let line = std::fs::read_to_string(&path).unwrap();
+        let body: Json = serde_json::from_str(line.trim()).unwrap();
+        let resource = &body["resourceSpans"][0];
+        let service_name = resource["resource"]["attributes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|attribute| attribute["key"] == "service.name")
+            .unwrap();
+        assert_eq!(service_name["value"]["stringValue"], NAME);
+        let span = &resource["scopeSpans"][0]["spans"][0];
+        assert_eq!(span["name"], "GET /entities");
+        assert_eq!(span["kind"], 2);
+        assert_eq!(span["parentSpanId"], "");
+        assert_eq!(span["attributes"][0]["value"]["intValue"], "200");
+        assert_eq!(span["traceId"].as_str().unwrap().len(), 32);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn traces_url_appends_to_the_endpoint_path() {
+        let url = |endpoint: &str| traces_url(&Url::parse(endpoint).unwrap()).unwrap();
+
+        assert_eq!(
+            url("http://collector:4318").as_str(),
+            "http://collector:4318/v1/traces"
+        );
+        assert_eq!(
+            url("http://collector:4318/otel").as_str(),
+            "http://collector:4318/otel/v1/traces"
+        );
+        assert_eq!(
+            url("http://collector:4318/otel/").as_str(),
+            "http://collector:4318/otel/v1/traces"
+        );
+    }
+
+    #[test]
+    fn inject_without_span_adds_nothing() {
+        let mut headers = HeaderMap::new();
+        inject(&mut headers);
+        assert!(headers.is_empty());
+    }
+}
@@ -2,16 +2,17 @@
 //!
 //! The provides two functions one function run_in_tokio creates and sends the function to tokio.
 //! The second function run_in_tokio_with_cancel allows the creation of a CancellationToken which can be used to shut down the tokio async.
+//! cancel_on_signal connects SIGTERM and SIGINT to a CancellationToken.
 
 use crate::error::MyError;
 use futures::Future;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use serde::Deserialize;
 use tokio::runtime::{self, Runtime};
 use tokio_util::sync::CancellationToken;
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct ThreadRuntime {
     pub threads: usize,
     pub stack_size: usize,
@@ -81,3 +82,26 @@ where
         }
     })
 }
+
+/// Cancel the token on SIGTERM (Kubernetes pod termination) or SIGINT (Ctrl-C)
+pub async fn cancel_on_signal(ct: CancellationToken) -> Result<(), MyError> {
+    #[cfg(unix)]
+    let terminate = async {
+        let mut sigterm =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        sigterm.recv().await;
+        Ok::<_, MyError>("SIGTERM")
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<Result<&str, MyError>>();
+
+    let signal = tokio::select! {
+        _ = ct.cancelled() => return Ok(()),
+        interrupt = tokio::signal::ctrl_c() => interrupt.map(|_| "SIGINT")?,
+        terminate = terminate => terminate?,
+    };
+
+    warn!("Received {signal}, shutting down");
+    ct.cancel();
+    Ok(())
+}
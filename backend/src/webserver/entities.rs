@@ -7,17 +7,24 @@ use axum::{
     routing::{get, post},
 };
 use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, types::Json};
+use utoipa::{IntoParams, OpenApi, ToSchema};
 
+use crate::webserver::entity_types::validate_attributes;
+use crate::webserver::labels::{Labels, SelectorQuery, validate_labels};
+use crate::webserver::subgraph;
+use crate::webserver::teams::{self, Team};
 use crate::webserver::{ListPages, PageOptions};
 use crate::{
     MyState,
     error::MyError,
-    webserver::{AppJson, DbBigSerial},
+    webserver::{AppJson, DbBigSerial, ErrorResponse},
 };
 
-#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
 pub struct Entity {
     #[serde(default)]
+    #[schema(value_type = Option<i64>)]
     pub id: Option<DbBigSerial>,
     pub name: String,
     #[sqlx(rename = "type")]
@@ -32,47 +39,157 @@ pub struct Entity {
     #[serde(default)]
     pub y: Option<i32>,
     pub attributes: serde_json::Value,
+    #[serde(default)]
+    #[sqlx(json)]
+    #[schema(value_type = BTreeMap<String, String>)]
+    pub labels: Labels,
+    /// Team accountable for the entity, unset when nobody owns it
+    #[serde(default)]
+    #[schema(value_type = Option<i64>)]
+    pub owner_team_id: Option<DbBigSerial>,
+}
+
+/// Enough of an entity to name it in reports
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct EntitySummary {
+    #[schema(value_type = i64)]
+    pub id: DbBigSerial,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub entity_type: String,
+}
+
+impl From<&Entity> for EntitySummary {
+    fn from(entity: &Entity) -> Self {
+        EntitySummary {
+            id: entity.id.unwrap_or_default(),
+            name: entity.name.clone(),
+            entity_type: entity.entity_type.clone(),
+        }
+    }
+}
+
+/// Ownership filters for the entity list
+#[derive(Debug, Deserialize, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OwnerQuery {
+    /// Only entities owned by this team
+    #[param(value_type = Option<i64>)]
+    pub owner: Option<DbBigSerial>,
+    /// Only entities without an owner
+    #[serde(default)]
+    pub unowned: bool,
+    /// Only entities that at least one other entity depends on
+    #[serde(default)]
+    pub depended_on: bool,
+}
+
+/// An entity's owning team, including its escalation contacts
+#[derive(Debug, Serialize, ToSchema)]
+pub struct EntityOwner {
+    #[schema(value_type = i64)]
+    pub entity_id: DbBigSerial,
+    pub team: Option<Team>,
 }
 
 pub fn entity_apis() -> Router<MyState> {
     Router::new()
         .route("/", post(create).get(list))
         .route("/{id}", get(read).put(update).delete(delete))
+        .route("/{id}/owner", get(owner))
+        .route("/{id}/graph", get(subgraph::neighbourhood))
 }
 
+#[derive(OpenApi)]
+#[openapi(paths(list, create, read, owner, update, delete))]
+pub(crate) struct EntityApi;
+
+/// List entity ids, optionally restricted by a label selector and ownership
+///
+/// For example all critical dependencies owned by team 3, or every entity without an owner.
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -v http://localhost:8080/entities\?selector\=team%3Dpayments,tier%20in%20\(critical\)
+/// curl -v http://localhost:8080/entities\?owner\=3\&depended_on\=true\&selector\=tier%3Dcritical
+/// curl -v http://localhost:8080/entities\?unowned\=true
+/// ```
+#[utoipa::path(
+    get,
+    path = "/entities",
+    tag = "entities",
+    params(PageOptions, SelectorQuery, OwnerQuery),
+    responses(
+        (status = 200, description = "A page of entity ids", body = ListPages),
+        (status = 422, description = "Malformed selector", body = ErrorResponse),
+    )
+)]
 async fn list(
     State(state): State<MyState>,
     Query(options): Query<PageOptions>,
+    selector: SelectorQuery,
+    Query(ownership): Query<OwnerQuery>,
 ) -> Result<AppJson<ListPages>, MyError> {
     let options = PageOptions::defaulting(options);
 
-    // If we want filtering by type later, we can add it to PageOptions or a specialized struct
-    // For now simple pagination
-    let items = sqlx::query_as::<_, Entity>(
-        r#"SELECT id, name, type, p99_millis, p95_millis, availability, throughput_rps, x, y, attributes FROM entities
-        LIMIT $1 OFFSET $2
-        "#,
-    )
-    .bind(options.size)
-    .bind(options.page.unwrap() * options.size.unwrap())
-    .fetch_all(&state.db_state.pool_pg)
-    .await?;
+    let mut query = QueryBuilder::new("SELECT id FROM entities WHERE TRUE");
+    selector.selector.push_sql(&mut query, "labels");
+    if let Some(owner) = ownership.owner {
+        query.push(" AND owner_team_id = ").push_bind(owner);
+    }
+    if ownership.unowned {
+        query.push(" AND owner_team_id IS NULL");
+    }
+    if ownership.depended_on {
+        query.push(
+            " AND EXISTS (SELECT 1 FROM relationships r WHERE r.to_id = entities.id AND r.from_id <> entities.id)",
+        );
+    }
+    query
+        .push(" ORDER BY id LIMIT ")
+        .push_bind(options.size)
+        .push(" OFFSET ")
+        .push_bind(options.page.unwrap() * options.size.unwrap());
+
+    let ids = query
+        .build_query_scalar::<DbBigSerial>()
+        .fetch_all(&state.db_state.pool())
+        .await?;
 
     let list_ids = ListPages {
         pagination: options,
-        ids: items.iter().map(|u| u.id.unwrap()).collect(),
+        ids,
     };
 
     Ok(AppJson(list_ids))
 }
 
+#[utoipa::path(
+    post,
+    path = "/entities",
+    tag = "entities",
+    request_body = Entity,
+    responses(
+        (status = 201, description = "Entity created", body = Entity),
+        (status = 422, description = "Attributes or labels not valid", body = ErrorResponse),
+    )
+)]
 async fn create(
     State(state): State<MyState>,
     AppJson(payload): AppJson<Entity>,
 ) -> Result<impl IntoResponse, MyError> {
+    validate_attributes(
+        &state.db_state.pool(),
+        &payload.entity_type,
+        &payload.attributes,
+    )
+    .await?;
+    validate_labels(&payload.labels)?;
+
     let entity = sqlx::query_as::<_, Entity>(
-        r#"INSERT INTO entities (name, type, p99_millis, p95_millis, availability, throughput_rps, x, y, attributes)
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING *"#,
+        r#"INSERT INTO entities (name, type, p99_millis, p95_millis, availability, throughput_rps, x, y, attributes, labels, owner_team_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) RETURNING *"#,
     )
     .bind(payload.name)
     .bind(payload.entity_type)
@@ -83,39 +200,110 @@ async fn create(
     .bind(payload.x)
     .bind(payload.y)
     .bind(payload.attributes)
-    .fetch_one(&state.db_state.pool_pg)
+    .bind(Json(payload.labels))
+    .bind(payload.owner_team_id)
+    .fetch_one(&state.db_state.pool())
     .await?;
 
     Ok((StatusCode::CREATED, AppJson(entity)).into_response())
 }
 
+#[utoipa::path(
+    get,
+    path = "/entities/{id}",
+    tag = "entities",
+    params(("id" = i64, Path, description = "Entity id")),
+    responses(
+        (status = 200, description = "The entity", body = Entity),
+        (status = 404, description = "No entity with the id", body = ErrorResponse),
+    )
+)]
 async fn read(
     Path(id): Path<DbBigSerial>,
     State(state): State<MyState>,
 ) -> Result<AppJson<Entity>, MyError> {
     let entity = sqlx::query_as::<_, Entity>("SELECT * FROM entities WHERE id = $1")
         .bind(id)
-        .fetch_one(&state.db_state.pool_pg)
+        .fetch_one(&state.db_state.pool())
         .await?;
 
     Ok(AppJson(entity))
 }
 
+/// Show the team owning an entity and who to escalate to
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -v http://localhost:8080/entities/1/owner
+/// ```
+#[utoipa::path(
+    get,
+    path = "/entities/{id}/owner",
+    tag = "entities",
+    params(("id" = i64, Path, description = "Entity id")),
+    responses(
+        (status = 200, description = "The owning team, null when unowned", body = EntityOwner),
+        (status = 404, description = "No entity with the id", body = ErrorResponse),
+    )
+)]
+async fn owner(
+    Path(id): Path<DbBigSerial>,
+    State(state): State<MyState>,
+) -> Result<AppJson<EntityOwner>, MyError> {
+    let owner_team_id = sqlx::query_scalar::<_, Option<DbBigSerial>>(
+        "SELECT owner_team_id FROM entities WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_one(&state.db_state.pool())
+    .await?;
+
+    let team = match owner_team_id {
+        Some(team_id) => teams::fetch(&state.db_state.pool(), team_id).await?,
+        None => None,
+    };
+
+    Ok(AppJson(EntityOwner {
+        entity_id: id,
+        team,
+    }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/entities/{id}",
+    tag = "entities",
+    params(("id" = i64, Path, description = "Entity id, matching the id in the body")),
+    request_body = Entity,
+    responses(
+        (status = 200, description = "The updated entity", body = Entity),
+        (status = 404, description = "No entity with the id", body = ErrorResponse),
+        (status = 422, description = "Path and body ids differ, or attributes or labels not valid", body = ErrorResponse),
+    )
+)]
 async fn update(
     State(state): State<MyState>,
     Path(id): Path<DbBigSerial>,
     AppJson(payload): AppJson<Entity>,
 ) -> Result<impl IntoResponse, MyError> {
     if payload.id.is_none() || id != payload.id.unwrap() {
-        return Err(MyError::Message(
-            "ids on path and body must match for update",
+        return Err(MyError::Validation(
+            "ids on path and body must match for update".into(),
         ));
     }
 
+    validate_attributes(
+        &state.db_state.pool(),
+        &payload.entity_type,
+        &payload.attributes,
+    )
+    .await?;
+    validate_labels(&payload.labels)?;
+
     let entity = sqlx::query_as::<_, Entity>(
         r#"
         UPDATE entities
-        SET name = $2, type = $3, p99_millis = $4, p95_millis = $5, availability = $6, throughput_rps = $7, x = $8, y = $9, attributes = $10
+        SET name = $2, type = $3, p99_millis = $4, p95_millis = $5, availability = $6, throughput_rps = $7, x = $8, y = $9, attributes = $10, labels = $11, owner_team_id = $12
         WHERE id = $1
         RETURNING *
         "#,
@@ -130,19 +318,31 @@ async fn update(
     .bind(payload.x)
     .bind(payload.y)
     .bind(payload.attributes)
-    .fetch_one(&state.db_state.pool_pg)
+    .bind(Json(payload.labels))
+    .bind(payload.owner_team_id)
+    .fetch_one(&state.db_state.pool())
     .await?;
 
     Ok(AppJson(entity))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/entities/{id}",
+    tag = "entities",
+    params(("id" = i64, Path, description = "Entity id")),
+    responses(
+        (status = 200, description = "The deleted entity", body = Entity),
+        (status = 404, description = "No entity with the id", body = ErrorResponse),
+    )
+)]
 async fn delete(
     State(state): State<MyState>,
     Path(id): Path<DbBigSerial>,
 ) -> Result<AppJson<Entity>, MyError> {
     let entity = sqlx::query_as::<_, Entity>("DELETE FROM entities WHERE id = $1 RETURNING *")
         .bind(id)
-        .fetch_one(&state.db_state.pool_pg)
+        .fetch_one(&state.db_state.pool())
         .await?;
 
     Ok(AppJson(entity))
@@ -0,0 +1,228 @@
+use axum::http::StatusCode;
+use axum::{
+    Router,
+    extract::{Path, State},
+    response::IntoResponse,
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+
+use crate::{MyState, error::MyError, webserver::AppJson};
+
+/// A registered entity type and the JSON Schema its entities' `attributes` must satisfy
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct EntityType {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub attributes_schema: Value,
+}
+
+pub fn entity_type_apis() -> Router<MyState> {
+    Router::new()
+        .route("/", post(create).get(list))
+        .route("/{name}", get(read).put(update).delete(delete))
+}
+
+/// Compile a JSON Schema, rejecting schemas that are not themselves valid
+pub(crate) fn compile(schema: &Value) -> Result<jsonschema::Validator, MyError> {
+    jsonschema::validator_for(schema)
+        .map_err(|err| MyError::Validation(format!("invalid attributes schema: {err}")))
+}
+
+/// Check `attributes` against `schema`, listing every violation in the error
+pub fn validate(schema: &Value, attributes: &Value) -> Result<(), MyError> {
+    let validator = compile(schema)?;
+
+    let problems: Vec<String> = validator
+        .iter_errors(attributes)
+        .map(|err| format!("{}: {err}", err.instance_path()))
+        .collect();
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(MyError::Validation(format!(
+            "attributes do not match schema: {}",
+            problems.join("; ")
+        )))
+    }
+}
+
+/// Validate entity attributes against the schema registered for `entity_type`
+pub async fn validate_attributes(
+    pool: &PgPool,
+    entity_type: &str,
+    attributes: &Value,
+) -> Result<(), MyError> {
+    let schema = sqlx::query_scalar::<_, Value>(
+        "SELECT attributes_schema FROM entity_types WHERE name = $1",
+    )
+    .bind(entity_type)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| MyError::Validation(format!("unknown entity type `{entity_type}`")))?;
+
+    validate(&schema, attributes)
+}
+
+/// List all entity types with their schemas so clients can render attribute forms
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -v http://localhost:8080/entity-types
+/// ```
+async fn list(State(state): State<MyState>) -> Result<AppJson<Vec<EntityType>>, MyError> {
+    let types = sqlx::query_as::<_, EntityType>("SELECT * FROM entity_types ORDER BY name")
+        .fetch_all(&state.db_state.pool())
+        .await?;
+
+    Ok(AppJson(types))
+}
+
+/// Register a new entity type
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -X POST http://localhost:8080/entity-types \
+///      -H "Content-Type: application/json" \
+///      -d '{"name": "queue", "attributes_schema": {"type": "object", "properties": {"broker": {"type": "string"}}}}'
+/// ```
+async fn create(
+    State(state): State<MyState>,
+    AppJson(payload): AppJson<EntityType>,
+) -> Result<impl IntoResponse, MyError> {
+    compile(&payload.attributes_schema)?;
+
+    let entity_type = sqlx::query_as::<_, EntityType>(
+        "INSERT INTO entity_types (name, description, attributes_schema) VALUES ($1, $2, $3) RETURNING *",
+    )
+    .bind(payload.name)
+    .bind(payload.description)
+    .bind(payload.attributes_schema)
+    .fetch_one(&state.db_state.pool())
+    .await?;
+
+    Ok((StatusCode::CREATED, AppJson(entity_type)).into_response())
+}
+
+async fn read(
+    Path(name): Path<String>,
+    State(state): State<MyState>,
+) -> Result<AppJson<EntityType>, MyError> {
+    let entity_type = sqlx::query_as::<_, EntityType>("SELECT * FROM entity_types WHERE name = $1")
+        .bind(name)
+        .fetch_one(&state.db_state.pool())
+        .await?;
+
+    Ok(AppJson(entity_type))
+}
+
+/// Update the description and schema of an entity type
+///
+/// Existing entities are not revalidated, the new schema applies on their next update.
+async fn update(
+    State(state): State<MyState>,
+    Path(name): Path<String>,
+    AppJson(payload): AppJson<EntityType>,
+) -> Result<impl IntoResponse, MyError> {
+    if name != payload.name {
+        return Err(MyError::Validation(
+            "names on path and body must match for update".into(),
+        ));
+    }
+
+    compile(&payload.attributes_schema)?;
+
+    let entity_type = sqlx::query_as::<_, EntityType>(
+        r#"
+        UPDATE entity_types
+        SET description = $2, attributes_schema = $3
+        WHERE name = $1
+        RETURNING *
+        "#,
+    )
+    .bind(name)
+    .bind(payload.description)
+    .bind(payload.attributes_schema)
+    .fetch_one(&state.db_state.pool())
+    .await?;
+
+    Ok(AppJson(entity_type))
+}
+
+/// Delete an entity type, refused while any entity still uses it
+async fn delete(
+    State(state): State<MyState>,
+    Path(name): Path<String>,
+) -> Result<AppJson<EntityType>, MyError> {
+    let in_use = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM entities WHERE type = $1")
+        .bind(&name)
+        .fetch_one(&state.db_state.pool())
+        .await?;
+
+    if in_use > 0 {
+        return Err(MyError::Validation(format!(
+            "entity type `{name}` is used by {in_use} entities"
+        )));
+    }
+
+    let in_rules = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM relationship_type_rules WHERE from_type = $1 OR to_type = $1",
+    )
+    .bind(&name)
+    .fetch_one(&state.db_state.pool())
+    .await?;
+
+    if in_rules > 0 {
+        return Err(MyError::Validation(format!(
+            "entity type `{name}` is used by {in_rules} relationship type rules"
+        )));
+    }
+
+    let entity_type =
+        sqlx::query_as::<_, EntityType>("DELETE FROM entity_types WHERE name = $1 RETURNING *")
+            .bind(name)
+            .fetch_one(&state.db_state.pool())
+            .await?;
+
+    Ok(AppJson(entity_type))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn validate_reports_each_problem() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "cpus": {"type": "integer", "minimum": 1},
+                "os": {"type": "string"}
+            },
+            "required": ["os"]
+        });
+
+        assert!(validate(&schema, &json!({"os": "linux", "cpus": 4})).is_ok());
+
+        match validate(&schema, &json!({"cpus": 0})) {
+            Err(MyError::Validation(msg)) => {
+                assert!(msg.contains("/cpus"), "{msg}");
+                assert!(msg.contains("os"), "{msg}");
+            }
+            other => panic!("expected validation error, got {other:?}"),
+        }
+
+        assert!(matches!(
+            validate(&json!({"type": 12}), &json!({})),
+            Err(MyError::Validation(_))
+        ));
+    }
+}
@@ -0,0 +1,48 @@
+use async_graphql::http::GraphiQLSource;
+use axum::{
+    Extension, Router,
+    extract::State,
+    response::Html,
+    routing::{get, post},
+};
+
+use crate::{
+    MyState,
+    error::MyError,
+    graphql::{self, CaptureSchema, GraphqlConfig},
+    webserver::{AppJson, api_prefix},
+};
+
+pub fn graphql_apis(config: &GraphqlConfig) -> Router<MyState> {
+    let router = Router::new().route("/", post(execute));
+    let router = if config.graphiql {
+        router.route("/", get(graphiql))
+    } else {
+        router
+    };
+    router.layer(Extension(graphql::schema(config)))
+}
+
+/// Run a GraphQL query, errors are reported in the response body
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -X POST http://localhost:8080/graphql \
+///      -H "Content-Type: application/json" \
+///      -d '{"query": "{ entity(id: 1) { name dependencies(depth: 2) { depth entity { name } } } }"}'
+/// ```
+async fn execute(
+    State(state): State<MyState>,
+    Extension(schema): Extension<CaptureSchema>,
+    AppJson(request): AppJson<async_graphql::Request>,
+) -> Result<AppJson<async_graphql::Response>, MyError> {
+    let request = graphql::with_loader(request, state.db_state.clone());
+    Ok(AppJson(schema.execute(request).await))
+}
+
+/// GraphiQL for exploring the schema from a browser
+async fn graphiql(State(state): State<MyState>) -> Html<String> {
+    let endpoint = format!("{}/graphql", api_prefix(&state.config.webservice.url));
+    Html(GraphiQLSource::build().endpoint(&endpoint).finish())
+}
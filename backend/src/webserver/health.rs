@@ -0,0 +1,51 @@
+use axum::{
+    Router,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use serde::Serialize;
+
+use crate::{MyState, health::readiness, webserver::AppJson};
+
+#[derive(Serialize, Debug)]
+pub struct Liveness {
+    pub alive: bool,
+}
+
+pub fn health_apis() -> Router<MyState> {
+    Router::new()
+        .route("/alive", get(alive))
+        .route("/ready", get(ready))
+}
+
+/// The web service is answering requests
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -v http://localhost:8080/health/alive
+/// ```
+async fn alive() -> AppJson<Liveness> {
+    AppJson(Liveness { alive: true })
+}
+
+/// Ready to serve traffic, `503 Service Unavailable` with the failing checks otherwise
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -v http://localhost:8080/health/ready
+/// ```
+async fn ready(State(state): State<MyState>) -> Response {
+    let readiness = readiness(&state.health, &state.db_state.pool()).await;
+
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, AppJson(readiness)).into_response()
+}
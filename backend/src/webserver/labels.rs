@@ -0,0 +1,344 @@
+//! Kubernetes style labels and label selectors
+//!
+//! Entities and relationships carry `labels`, a flat map of string keys to string values
+//! (e.g. `team=payments`, `tier=critical`) stored in an indexed `JSONB` column.
+//!
+//! A selector is a comma separated list of requirements which must all hold:
+//!
+//! - `key=value` or `key==value`: the label is set to `value`
+//! - `key!=value`: the label is missing or set to something else
+//! - `key in (a,b)`: the label is set to one of the values
+//! - `key notin (a,b)`: the label is missing or set to none of the values
+//! - `key`: the label is set to any value
+//! - `!key`: the label is missing
+//!
+//! For example `team=payments,tier in (critical,high),!deprecated`.
+
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use axum::{
+    extract::{FromRequestParts, Query},
+    http::request::Parts,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use sqlx::{Postgres, QueryBuilder, types::Json};
+use utoipa::IntoParams;
+
+use crate::error::MyError;
+
+pub type Labels = BTreeMap<String, String>;
+
+const MAX_LABEL_LENGTH: usize = 63;
+
+fn valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.len() <= MAX_LABEL_LENGTH
+        && key.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./".contains(c))
+}
+
+fn valid_value(value: &str) -> bool {
+    value.len() <= MAX_LABEL_LENGTH
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+}
+
+/// Check label keys and values only use the characters allowed in selectors
+pub fn validate_labels(labels: &Labels) -> Result<(), MyError> {
+    for (key, value) in labels {
+        if !valid_key(key) {
+            return Err(MyError::Validation(format!("invalid label key `{key}`")));
+        }
+        if !valid_value(value) {
+            return Err(MyError::Validation(format!(
+                "invalid value `{value}` for label `{key}`"
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Requirement {
+    Equals(String, String),
+    NotEquals(String, String),
+    In(String, Vec<String>),
+    NotIn(String, Vec<String>),
+    Exists(String),
+    NotExists(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LabelSelector(pub Vec<Requirement>);
+
+impl LabelSelector {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Check the selector against labels held in memory
+    pub fn matches(&self, labels: &Labels) -> bool {
+        self.0.iter().all(|requirement| match requirement {
+            Requirement::Equals(key, value) => labels.get(key) == Some(value),
+            Requirement::NotEquals(key, value) => labels.get(key) != Some(value),
+            Requirement::In(key, values) => labels.get(key).is_some_and(|v| values.contains(v)),
+            Requirement::NotIn(key, values) => !labels.get(key).is_some_and(|v| values.contains(v)),
+            Requirement::Exists(key) => labels.contains_key(key),
+            Requirement::NotExists(key) => !labels.contains_key(key),
+        })
+    }
+
+    /// Append ` AND <condition>` for every requirement against the JSONB `column`
+    pub fn push_sql(&self, query: &mut QueryBuilder<'_, Postgres>, column: &str) {
+        for requirement in &self.0 {
+            query.push(" AND ");
+            match requirement {
+                Requirement::Equals(key, value) => {
+                    query.push(format!("{column} @> "));
+                    query.push_bind(Json(Labels::from([(key.clone(), value.clone())])));
+                }
+                Requirement::NotEquals(key, value) => {
+                    query.push(format!("NOT {column} @> "));
+                    query.push_bind(Json(Labels::from([(key.clone(), value.clone())])));
+                }
+                Requirement::In(key, values) => {
+                    query.push(format!("{column} ->> "));
+                    query.push_bind(key.clone());
+                    query.push(" = ANY(");
+                    query.push_bind(values.clone());
+                    query.push(")");
+                }
+                Requirement::NotIn(key, values) => {
+                    query.push(format!("NOT COALESCE({column} ->> "));
+                    query.push_bind(key.clone());
+                    query.push(" = ANY(");
+                    query.push_bind(values.clone());
+                    query.push("), FALSE)");
+                }
+                Requirement::Exists(key) => {
+                    query.push(format!("{column} ? "));
+                    query.push_bind(key.clone());
+                }
+                Requirement::NotExists(key) => {
+                    query.push(format!("NOT {column} ? "));
+                    query.push_bind(key.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Split on commas that are not inside a `( ... )` value list
+fn split_requirements(selector: &str) -> Result<Vec<&str>, String> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (idx, c) in selector.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Err("unbalanced `)`".into()),
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&selector[start..idx]);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err("unbalanced `(`".into());
+    }
+    parts.push(&selector[start..]);
+    Ok(parts)
+}
+
+fn parse_key(key: &str) -> Result<String, String> {
+    let key = key.trim();
+    if valid_key(key) {
+        Ok(key.to_owned())
+    } else {
+        Err(format!("invalid label key `{key}`"))
+    }
+}
+
+fn parse_value(value: &str) -> Result<String, String> {
+    let value = value.trim();
+    if valid_value(value) {
+        Ok(value.to_owned())
+    } else {
+        Err(format!("invalid label value `{value}`"))
+    }
+}
+
+fn parse_set(set: &str) -> Result<Vec<String>, String> {
+    let inner = set
+        .trim()
+        .strip_prefix('(')
+        .and_then(|s| s.strip_suffix(')'))
+        .ok_or_else(|| format!("expected `(value, ...)` but found `{}`", set.trim()))?;
+    inner.split(',').map(parse_value).collect()
+}
+
+fn parse_requirement(text: &str) -> Result<Requirement, String> {
+    let text = text.trim();
+
+    if let Some((key, value)) = text.split_once("!=") {
+        return Ok(Requirement::NotEquals(parse_key(key)?, parse_value(value)?));
+    }
+    if let Some((key, value)) = text.split_once("==").or_else(|| text.split_once('=')) {
+        return Ok(Requirement::Equals(parse_key(key)?, parse_value(value)?));
+    }
+    if let Some(key) = text.strip_prefix('!') {
+        return Ok(Requirement::NotExists(parse_key(key)?));
+    }
+    if let Some((key, set)) = text.split_once(" notin ") {
+        return Ok(Requirement::NotIn(parse_key(key)?, parse_set(set)?));
+    }
+    if let Some((key, set)) = text.split_once(" in ") {
+        return Ok(Requirement::In(parse_key(key)?, parse_set(set)?));
+    }
+    Ok(Requirement::Exists(parse_key(text)?))
+}
+
+impl FromStr for LabelSelector {
+    type Err = String;
+
+    fn from_str(selector: &str) -> Result<Self, Self::Err> {
+        if selector.trim().is_empty() {
+            return Ok(LabelSelector::default());
+        }
+        split_requirements(selector)?
+            .into_iter()
+            .map(parse_requirement)
+            .collect::<Result<Vec<_>, _>>()
+            .map(LabelSelector)
+    }
+}
+
+impl fmt::Display for LabelSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let requirements: Vec<String> = self
+            .0
+            .iter()
+            .map(|requirement| match requirement {
+                Requirement::Equals(key, value) => format!("{key}={value}"),
+                Requirement::NotEquals(key, value) => format!("{key}!={value}"),
+                Requirement::In(key, values) => format!("{key} in ({})", values.join(",")),
+                Requirement::NotIn(key, values) => format!("{key} notin ({})", values.join(",")),
+                Requirement::Exists(key) => key.clone(),
+                Requirement::NotExists(key) => format!("!{key}"),
+            })
+            .collect();
+        write!(f, "{}", requirements.join(","))
+    }
+}
+
+impl<'de> Deserialize<'de> for LabelSelector {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for LabelSelector {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Query parameters shared by endpoints that can be scoped by labels
+///
+/// Extracted directly rather than through `Query` so a malformed selector is answered with
+/// `422` and an `ErrorResponse` like the other validation errors.
+#[derive(Deserialize, Debug, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SelectorQuery {
+    /// Label selector such as `team=payments,tier in (critical,high),!deprecated`
+    #[serde(default)]
+    #[param(value_type = Option<String>)]
+    pub selector: LabelSelector,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for SelectorQuery {
+    type Rejection = MyError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<SelectorQuery>::from_request_parts(parts, state)
+            .await
+            .map_err(|rejection| MyError::Validation(rejection.body_text()))?;
+        Ok(query)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_and_match_selectors() {
+        let selector: LabelSelector = "team=payments, tier in (critical,high),!deprecated,region"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            selector.0,
+            vec![
+                Requirement::Equals("team".into(), "payments".into()),
+                Requirement::In("tier".into(), vec!["critical".into(), "high".into()]),
+                Requirement::NotExists("deprecated".into()),
+                Requirement::Exists("region".into()),
+            ]
+        );
+        assert_eq!(
+            selector.to_string().parse::<LabelSelector>().unwrap(),
+            selector
+        );
+
+        let labels = Labels::from([
+            ("team".into(), "payments".into()),
+            ("tier".into(), "critical".into()),
+            ("region".into(), "eu".into()),
+        ]);
+        assert!(selector.matches(&labels));
+
+        let other: LabelSelector = "tier notin (critical),team!=payments".parse().unwrap();
+        assert!(!other.matches(&labels));
+        assert!(other.matches(&Labels::new()));
+    }
+
+    #[test]
+    fn reject_bad_selectors() {
+        assert!("team in (a,b".parse::<LabelSelector>().is_err());
+        assert!("te am=x".parse::<LabelSelector>().is_err());
+        assert!("team=pay ments".parse::<LabelSelector>().is_err());
+        assert!("".parse::<LabelSelector>().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn extract_selector_query() {
+        let parts = |uri: &str| {
+            axum::http::Request::get(uri)
+                .body(())
+                .unwrap()
+                .into_parts()
+                .0
+        };
+
+        let query =
+            SelectorQuery::from_request_parts(&mut parts("/?selector=team%3Dpayments"), &())
+                .await
+                .unwrap();
+        assert_eq!(query.selector.to_string(), "team=payments");
+
+        let query = SelectorQuery::from_request_parts(&mut parts("/"), &())
+            .await
+            .unwrap();
+        assert!(query.selector.is_empty());
+
+        let malformed =
+            SelectorQuery::from_request_parts(&mut parts("/?selector=team%20in%20(a"), &()).await;
+        assert!(matches!(malformed, Err(MyError::Validation(_))));
+    }
+}
@@ -0,0 +1,140 @@
+use axum::{Router, extract::State, routing::post};
+use serde::{Deserialize, Serialize};
+use sqlx::QueryBuilder;
+use tracing::info;
+
+use crate::{
+    MyState,
+    error::MyError,
+    graph::{
+        Graph,
+        layout::{LayoutAlgorithm, LayoutOptions, layout},
+    },
+    webserver::{AppJson, DbBigSerial, labels::LabelSelector},
+};
+
+#[derive(Deserialize, Debug)]
+pub struct LayoutRequest {
+    #[serde(default)]
+    pub algorithm: LayoutAlgorithm,
+    /// Entities to lay out, all entities when not set
+    #[serde(default)]
+    pub ids: Option<Vec<DbBigSerial>>,
+    /// Only lay out entities that do not yet have both `x` and `y`
+    #[serde(default)]
+    pub unplaced: bool,
+    /// Only lay out entities matching this label selector, e.g. `team=payments`
+    #[serde(default)]
+    pub selector: LabelSelector,
+    /// Write the computed coordinates into the `x`/`y` columns
+    #[serde(default)]
+    pub persist: bool,
+    #[serde(default)]
+    pub options: LayoutOptions,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Position {
+    pub id: DbBigSerial,
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LayoutResponse {
+    pub algorithm: LayoutAlgorithm,
+    pub persisted: bool,
+    pub positions: Vec<Position>,
+}
+
+pub fn layout_apis() -> Router<MyState> {
+    Router::new().route("/", post(compute))
+}
+
+/// Compute coordinates for all or a subset of the entities
+///
+/// Only relationships between the selected entities influence the layout.
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -X POST http://localhost:8080/layout \
+///      -H "Content-Type: application/json" \
+///      -d '{"algorithm": "layered", "unplaced": true, "persist": true}'
+/// ```
+async fn compute(
+    State(state): State<MyState>,
+    AppJson(request): AppJson<LayoutRequest>,
+) -> Result<AppJson<LayoutResponse>, MyError> {
+    request.options.validate()?;
+    let pool = &state.db_state.pool();
+
+    let graph = if request.ids.is_none() && !request.unplaced && request.selector.is_empty() {
+        Graph::load(pool).await?
+    } else {
+        let mut query = QueryBuilder::new("SELECT id FROM entities WHERE TRUE");
+        if let Some(ids) = &request.ids {
+            query
+                .push(" AND id = ANY(")
+                .push_bind(ids.clone())
+                .push(")");
+        }
+        if request.unplaced {
+            query.push(" AND (x IS NULL OR y IS NULL)");
+        }
+        request.selector.push_sql(&mut query, "labels");
+
+        let ids = query
+            .build_query_scalar::<DbBigSerial>()
+            .fetch_all(pool)
+            .await?;
+        Graph::load_subset(pool, &ids).await?
+    };
+
+    // Layout is CPU bound, keep it off the async workers
+    let algorithm = request.algorithm;
+    let options = request.options;
+    let positions = tokio::task::spawn_blocking(move || {
+        let points = layout(algorithm, graph.len(), &graph.edges(), &options);
+        graph
+            .entities
+            .iter()
+            .zip(points)
+            .map(|(entity, (x, y))| Position {
+                id: entity.id.unwrap(),
+                x: x.round() as i32,
+                y: y.round() as i32,
+            })
+            .collect::<Vec<Position>>()
+    })
+    .await
+    .map_err(|_| MyError::Message("Layout computation failed"))?;
+
+    if request.persist && !positions.is_empty() {
+        info!("Persisting layout of {} entities", positions.len());
+
+        let ids: Vec<DbBigSerial> = positions.iter().map(|p| p.id).collect();
+        let xs: Vec<i32> = positions.iter().map(|p| p.x).collect();
+        let ys: Vec<i32> = positions.iter().map(|p| p.y).collect();
+
+        sqlx::query(
+            r#"
+            UPDATE entities
+            SET x = placed.x, y = placed.y
+            FROM UNNEST($1::BIGINT[], $2::INTEGER[], $3::INTEGER[]) AS placed(id, x, y)
+            WHERE entities.id = placed.id
+            "#,
+        )
+        .bind(ids)
+        .bind(xs)
+        .bind(ys)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(AppJson(LayoutResponse {
+        algorithm: request.algorithm,
+        persisted: request.persist,
+        positions,
+    }))
+}
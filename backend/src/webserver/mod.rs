@@ -1,27 +1,48 @@
 pub mod entities;
+pub mod entity_types;
+pub mod graphql;
+pub mod health;
+pub mod labels;
+pub mod layout;
+pub mod openapi;
+pub mod paths;
+pub mod relationship_types;
 pub mod relationships;
+pub mod risk;
+pub mod scraper;
+pub mod search;
+pub mod simulate;
+pub mod slis;
+pub mod spof;
+pub mod subgraph;
+pub mod teams;
 pub mod users;
 
 use axum::{
     Router,
-    extract::{FromRequest, MatchedPath},
+    extract::{FromRequest, MatchedPath, Request, State},
+    middleware::{self, Next},
     response::{IntoResponse, Response},
     routing::get,
 };
 use axum_prometheus::PrometheusMetricLayer;
 use reqwest::StatusCode;
-use tower_http::trace::{DefaultOnFailure, DefaultOnRequest, DefaultOnResponse, TraceLayer};
-use tracing::{Level, info};
+use tower_http::trace::{DefaultOnFailure, DefaultOnRequest, TraceLayer};
+use tracing::{Level, Span, info, warn};
 
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use sqlx::{Pool, Postgres, types::Decimal};
+use serde_with::{DurationSeconds, serde_as};
+use sqlx::types::Decimal;
+use std::future::IntoFuture;
 use std::net::SocketAddr;
+use std::time::Duration;
 use tokio_util::sync::CancellationToken;
+use utoipa::{IntoParams, ToSchema};
 
-use crate::{MyState, error::MyError, webserver::users::User};
+use crate::{MyState, error::MyError, forwarding, telemetry, webserver::users::User};
 
 /// Postgres does not support unsigned int so we use i64 to represent the BIGSERIAL type which is a BIGINT in SQL
-type DbBigSerial = i64;
+pub(crate) type DbBigSerial = i64;
 
 #[derive(Deserialize, Serialize, Debug, sqlx::FromRow)]
 pub struct DbId {
@@ -45,27 +66,38 @@ impl<'de> serde_with::DeserializeAs<'de, Decimal> for SerializeDecimal {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum SortOrder {
     Asc,
+    #[default]
     Desc,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct PageSort {
     #[serde(alias = "sortProperty")]
     pub property: String,
-    #[serde(alias = "sortOrder")]
+    /// Descending unless given
+    #[serde(alias = "sortOrder", default)]
     pub direction: SortOrder,
 }
 
 // The query parameters for list_todos.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct PageOptions {
+    /// Page number starting at 0, default 0
+    #[schema(value_type = Option<i64>)]
+    #[param(value_type = Option<i64>)]
     pub page: Option<DbBigSerial>,
+    /// Ids per page, default 5
+    #[schema(value_type = Option<i64>)]
+    #[param(value_type = Option<i64>)]
     pub size: Option<DbBigSerial>,
+    /// Not applied by the list endpoints yet
     #[serde(flatten)]
+    #[param(ignore)]
     pub sort: Option<PageSort>,
 }
 
@@ -101,12 +133,18 @@ impl PageOptions {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct ListPages {
+    #[schema(value_type = Vec<i64>)]
     ids: Vec<DbBigSerial>,
     pagination: PageOptions,
 }
 
+fn default_drain_timeout() -> Duration {
+    Duration::from_secs(20)
+}
+
+#[serde_as]
 #[derive(Deserialize, Debug, Clone)]
 pub struct WebServiceConfig {
     /// URL to start the webservice on
@@ -114,12 +152,22 @@ pub struct WebServiceConfig {
     /// as well as extracting the prefix of the served API
     pub url: url::Url,
     pub forwarding_headers: Vec<String>,
+    /// Time allowed for in-flight requests to complete once shutdown starts,
+    /// kept below the pod's termination grace period
+    #[serde_as(as = "DurationSeconds<u64>")]
+    #[serde(default = "default_drain_timeout")]
+    pub drain_timeout: Duration,
+    /// Serve a Swagger UI for `/openapi.json` at `/swagger-ui`
+    #[serde(default)]
+    pub openapi_ui: bool,
 }
 impl Default for WebServiceConfig {
     fn default() -> Self {
         Self {
             url: "http://127.0.0.1:1234/api".parse().unwrap(),
             forwarding_headers: vec![],
+            drain_timeout: default_drain_timeout(),
+            openapi_ui: false,
         }
     }
 }
@@ -143,20 +191,50 @@ where
     }
 }
 
-pub async fn start_app_api(
-    state: MyState,
-    pool_pg: Pool<Postgres>,
-    ct: CancellationToken,
-) -> Result<(), MyError> {
-    let mut prefix = state.config.webservice.url.path().to_string();
+/// Count responses into `count_good` and `count_fail`, a 5xx status being a failure
+async fn count_responses(State(state): State<MyState>, request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+
+    let counter = if response.status().is_server_error() {
+        &state.count_fail
+    } else {
+        &state.count_good
+    };
+    *counter.lock().await += 1;
+
+    response
+}
+
+/// Forward the configured `forwarding_headers` from the request to its response and to
+/// outbound calls made while handling it
+async fn forward_headers(State(state): State<MyState>, request: Request, next: Next) -> Response {
+    let forwarded = forwarding::capture(&state.live.forwarding_headers(), request.headers());
+    if forwarded.is_empty() {
+        return next.run(request).await;
+    }
+
+    let mut response = forwarding::scope(forwarded.clone(), next.run(request)).await;
+    forwarding::echo(&forwarded, response.headers_mut());
+    response
+}
+
+/// Path the API is served under, empty when served from the root
+pub(crate) fn api_prefix(url: &url::Url) -> String {
+    let mut prefix = url.path().to_string();
     if prefix.ends_with('/') && prefix.len() > 1 {
         prefix.pop();
     }
     if prefix == "/" {
         prefix = "".to_string();
     }
+    prefix
+}
+
+pub async fn start_app_api(state: MyState, ct: CancellationToken) -> Result<(), MyError> {
+    let prefix = api_prefix(&state.config.webservice.url);
 
     let shared_state = state.clone();
+    let live = state.live.clone();
 
     let metric_layer = PrometheusMetricLayer::new();
 
@@ -164,36 +242,79 @@ pub async fn start_app_api(
     let app = Router::new()
         .nest("/users", users::user_apis())
         .nest("/entities", entities::entity_apis())
+        .nest("/entity-types", entity_types::entity_type_apis())
         .nest("/relationships", relationships::relationship_apis())
+        .nest(
+            "/relationship-types",
+            relationship_types::relationship_type_apis(),
+        )
+        .nest("/layout", layout::layout_apis())
+        .nest("/paths", paths::paths_apis())
+        .nest("/scrape", scraper::scraper_apis())
+        .nest("/risk", risk::risk_apis())
+        .nest("/search", search::search_apis())
+        .nest("/simulate", simulate::simulate_apis())
+        .nest("/slis", slis::sli_apis())
+        .nest("/spof", spof::spof_apis())
+        .nest("/teams", teams::team_apis())
+        .nest("/health", health::health_apis())
+        .nest("/graphql", graphql::graphql_apis(&state.config.graphql))
+        .merge(openapi::openapi_apis())
         .route("/hello", get(|| async { "Hello, World!" }))
         // .route("/metrics", get(|| async move { metric_handle.render() }))
         .layer(
             TraceLayer::new_for_http()
-                .make_span_with(|request: &axum::http::Request<_>| {
+                .make_span_with(move |request: &axum::http::Request<_>| {
                     let matched_path = request
                         .extensions()
                         .get::<MatchedPath>()
                         .map(|matched_path| matched_path.as_str());
 
-                    tracing::info_span!(
+                    let span = tracing::info_span!(
                         "request",
                         method = ?request.method(),
                         uri = ?request.uri(),
-                        matched_path = ?matched_path,
-                    )
+                        matched_path = matched_path.unwrap_or_default(),
+                        request_id = tracing::field::Empty,
+                        user = tracing::field::Empty,
+                        status = tracing::field::Empty,
+                        latency_ms = tracing::field::Empty,
+                        otel.name = format!("{} {}", request.method(), matched_path.unwrap_or("")),
+                        otel.kind = "server",
+                    );
+                    live.logging().record_request(&span, request.headers());
+                    telemetry::set_parent(&span, request.headers());
+                    span
                 })
                 .on_request(DefaultOnRequest::new().level(Level::INFO))
-                .on_response(DefaultOnResponse::new().level(Level::INFO))
+                .on_response(
+                    |response: &axum::http::Response<_>, latency: Duration, span: &Span| {
+                        span.record("status", response.status().as_u16());
+                        span.record("latency_ms", latency.as_millis() as u64);
+                        info!("finished processing request");
+                    },
+                )
                 .on_failure(DefaultOnFailure::new().level(Level::ERROR)),
         )
         .layer(metric_layer)
+        .layer(middleware::from_fn_with_state(
+            shared_state.clone(),
+            count_responses,
+        ))
+        .layer(middleware::from_fn_with_state(
+            shared_state.clone(),
+            forward_headers,
+        ))
         .with_state(shared_state);
 
-    let prefix_app = if prefix.is_empty() {
+    let mut prefix_app = if prefix.is_empty() {
         app
     } else {
         Router::new().nest(&prefix, app)
     };
+    if state.config.webservice.openapi_ui {
+        prefix_app = prefix_app.merge(openapi::swagger_ui(&prefix));
+    }
 
     let host = state.config.webservice.url.host_str().unwrap_or("127.0.0.1");
     let port = state.config.webservice.url.port_or_known_default().unwrap_or(8080);
@@ -201,35 +322,53 @@ pub async fn start_app_api(
 
     // run our app with hyper, listening globally on port 3000
     let listener = tokio::net::TcpListener::bind(&address).await?;
-    let server = axum::serve(listener, prefix_app).with_graceful_shutdown(async move {
-        // The move is necessary as with_graceful_shutdown requires static lifetime
-        ct.cancelled().await
-    });
+    // On cancellation stop accepting connections and wait for in-flight requests to complete
+    let server = axum::serve(listener, prefix_app)
+        .with_graceful_shutdown(ct.clone().cancelled_owned())
+        .into_future();
 
     info!(
         "Server started on {address}{prefix}"
     );
 
-    Ok(server.await?)
+    let drain_timeout = state.config.webservice.drain_timeout;
+    let deadline = async {
+        ct.cancelled().await;
+        info!("Draining in-flight requests for up to {drain_timeout:?}");
+        tokio::time::sleep(drain_timeout).await;
+    };
+
+    tokio::select! {
+        served = server => Ok(served?),
+        _ = deadline => {
+            // Connections are served on their own tasks, which only end with the runtime
+            warn!("Drain deadline of {drain_timeout:?} passed, no longer waiting for requests");
+            Ok(())
+        }
+    }
+}
+
+/// Body of every error response
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ErrorResponse {
+    message: String,
 }
 
 impl IntoResponse for MyError {
     fn into_response(self) -> Response {
-        #[derive(Serialize)]
-        struct ErrorResponse {
-            message: String,
-        }
-
         let (status, message) = match self {
             MyError::Message(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg.to_string()),
             MyError::Cancelled => todo!(),
+            MyError::Validation(msg) => (StatusCode::UNPROCESSABLE_ENTITY, msg),
             MyError::HamsError(hams_error) => todo!(),
             MyError::Serde(error) => todo!(),
             MyError::Io(error) => todo!(),
+            MyError::ReqwestError(error) => (StatusCode::BAD_GATEWAY, format!("{error}")),
             MyError::SqlxError(error) => (StatusCode::NOT_FOUND, format!("{error}")),
             MyError::SqlxMigrateError(migrate_error) => todo!(),
-            MyError::ShutdownCheck => todo!(),
-            MyError::PreflightCheck => todo!(),
+            MyError::ShutdownCheck | MyError::PreflightCheck => {
+                (StatusCode::SERVICE_UNAVAILABLE, self.to_string())
+            }
             MyError::ParquetError(parquet_error) => todo!(),
             MyError::FigmentError(error) => todo!(),
             MyError::JsonRejection(rejection) => (rejection.status(), rejection.body_text()),
@@ -0,0 +1,138 @@
+//! OpenAPI 3 document of the REST API
+//!
+//! Generated by `utoipa` from the `#[utoipa::path]` annotations on the handlers and the schemas
+//! of their models. Each handler module describes its own paths, merged here. The document is
+//! served at `/openapi.json` with the API prefix as its server, and with `webservice.openapi_ui`
+//! a Swagger UI is served at `/swagger-ui`.
+//!
+//! `openapi.json` in the backend directory is the committed copy of the document, a test fails
+//! when it no longer matches what the handlers generate. Regenerate it with
+//! `UPDATE_OPENAPI=1 cargo test openapi`.
+
+use axum::{Router, extract::State, routing::get};
+use utoipa::{
+    OpenApi,
+    openapi::{self, Server},
+};
+use utoipa_swagger_ui::{Config, SwaggerUi};
+
+use crate::{
+    MyState,
+    webserver::{
+        AppJson, ErrorResponse, api_prefix, entities, paths, relationships, risk, simulate, spof,
+        subgraph, users,
+    },
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "service-capture",
+        description = "Capture the dependencies between services and infrastructure, with their SLOs and SLIs"
+    ),
+    tags(
+        (name = "users", description = "Users of the service"),
+        (name = "entities", description = "Services, consumers and infrastructure components"),
+        (name = "relationships", description = "Typed dependencies between entities"),
+        (name = "analysis", description = "Analyses of the whole dependency graph"),
+    ),
+    components(schemas(ErrorResponse))
+)]
+struct ApiDoc;
+
+/// The OpenAPI document of every described endpoint
+pub fn api_doc() -> openapi::OpenApi {
+    ApiDoc::openapi()
+        .merge_from(users::UserApi::openapi())
+        .merge_from(entities::EntityApi::openapi())
+        .merge_from(relationships::RelationshipApi::openapi())
+        .merge_from(subgraph::SubgraphApi::openapi())
+        .merge_from(paths::PathsApi::openapi())
+        .merge_from(spof::SpofApi::openapi())
+        .merge_from(simulate::SimulateApi::openapi())
+        .merge_from(risk::RiskApi::openapi())
+}
+
+pub fn openapi_apis() -> Router<MyState> {
+    Router::new().route("/openapi.json", get(document))
+}
+
+/// Swagger UI for the document, routed with the API prefix included
+pub fn swagger_ui(prefix: &str) -> Router {
+    SwaggerUi::new(format!("{prefix}/swagger-ui"))
+        .config(Config::from(format!("{prefix}/openapi.json")))
+        .into()
+}
+
+/// The OpenAPI document, with the API prefix as its server
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -v http://localhost:8080/openapi.json
+/// ```
+async fn document(State(state): State<MyState>) -> AppJson<openapi::OpenApi> {
+    let mut doc = api_doc();
+    let prefix = api_prefix(&state.config.webservice.url);
+    doc.servers = Some(vec![Server::new(if prefix.is_empty() {
+        "/".to_string()
+    } else {
+        prefix
+    })]);
+    AppJson(doc)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn openapi_document_has_not_drifted() {
+        let generated = api_doc().to_pretty_json().unwrap() + "\n";
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("openapi.json");
+        if std::env::var_os("UPDATE_OPENAPI").is_some() {
+            std::fs::write(&path, &generated).unwrap();
+        }
+
+        let committed = std::fs::read_to_string(&path).unwrap_or_default();
+        assert!(
+            committed == generated,
+            "openapi.json does not match the handlers, regenerate it with `UPDATE_OPENAPI=1 cargo test openapi`"
+        );
+    }
+
+    #[test]
+    fn documents_the_crud_endpoints() {
+        let doc = api_doc();
+        for path in [
+            "/users",
+            "/users/{id}",
+            "/entities",
+            "/entities/{id}",
+            "/entities/{id}/owner",
+            "/entities/{id}/graph",
+            "/relationships",
+            "/relationships/{id}",
+            "/paths",
+            "/spof",
+            "/simulate",
+            "/risk",
+        ] {
+            assert!(doc.paths.paths.contains_key(path), "{path} not documented");
+        }
+
+        let schemas = doc.components.unwrap().schemas;
+        for schema in [
+            "Entity",
+            "Relationship",
+            "User",
+            "ListPages",
+            "PageOptions",
+            "ErrorResponse",
+        ] {
+            assert!(schemas.contains_key(schema), "{schema} not documented");
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use axum::{
+    Router,
+    extract::{Query, State},
+    routing::get,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::{
+    MyState,
+    error::MyError,
+    graph::Graph,
+    webserver::{
+        AppJson, DbBigSerial, ErrorResponse, entities::EntitySummary, labels::SelectorQuery,
+    },
+};
+
+/// Longest path searched for `all`
+pub const MAX_HOPS: usize = 10;
+/// Most paths returned for `all`
+pub const MAX_PATHS: usize = 1000;
+/// Relationships examined by one `all` search before it gives up
+pub const MAX_STEPS: usize = 1_000_000;
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PathsQuery {
+    /// Id of the consuming entity
+    #[param(value_type = i64)]
+    pub from: DbBigSerial,
+    /// Id of the dependency
+    #[param(value_type = i64)]
+    pub to: DbBigSerial,
+    /// Also list every simple path, not only the shortest
+    #[serde(default)]
+    pub all: bool,
+    /// Longest path listed by `all`, 1 to 10
+    #[serde(default = "default_max_hops")]
+    #[param(default = 6)]
+    pub max_hops: usize,
+    /// Most paths listed by `all`, 1 to 1000
+    #[serde(default = "default_limit")]
+    #[param(default = 50)]
+    pub limit: usize,
+}
+
+fn default_max_hops() -> usize {
+    6
+}
+
+fn default_limit() -> usize {
+    50
+}
+
+/// One relationship followed from a consumer to its dependency
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Hop {
+    #[schema(value_type = i64)]
+    pub relationship_id: DbBigSerial,
+    pub relationship_type: String,
+    pub from: EntitySummary,
+    pub to: EntitySummary,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Paths {
+    #[schema(value_type = i64)]
+    pub from: DbBigSerial,
+    #[schema(value_type = i64)]
+    pub to: DbBigSerial,
+    /// Fewest hops from `from` to `to`, null when `to` is not a dependency of `from`
+    pub shortest: Option<Vec<Hop>>,
+    /// Paths visiting no entity twice, shortest first, only when `all` is requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub paths: Option<Vec<Vec<Hop>>>,
+    /// More paths may exist than were listed, `limit` was reached or the search gave up
+    pub truncated: bool,
+}
+
+#[derive(OpenApi)]
+#[openapi(paths(find))]
+pub(crate) struct PathsApi;
+
+pub fn paths_apis() -> Router<MyState> {
+    Router::new().route("/", get(find))
+}
+
+/// The relationships along a path, from the positions `Graph` reports them at
+fn hops(graph: &Graph, path: Vec<usize>) -> Vec<Hop> {
+    let entity =
+        |id: DbBigSerial| EntitySummary::from(&graph.entities[graph.index_of(id).unwrap()]);
+
+    path.into_iter()
+        .map(|edge| {
+            let relationship = &graph.relationships[edge];
+            Hop {
+                relationship_id: relationship.id.unwrap(),
+                relationship_type: relationship.relationship_type.clone(),
+                from: entity(relationship.from_id),
+                to: entity(relationship.to_id),
+            }
+        })
+        .collect()
+}
+
+/// Explain how one entity comes to depend on another
+///
+/// Paths follow relationships from consumer to dependency. The shortest path is always
+/// returned, `all=true` adds every path up to `max_hops` hops visiting no entity twice, at
+/// most `limit` of them. A search examining more than a million relationships stops with
+/// what it found and `truncated` set. With `selector`, paths only pass through matching
+/// entities.
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -v http://localhost:8080/paths\?from\=1\&to\=4
+/// curl -v http://localhost:8080/paths\?from\=1\&to\=4\&all\=true\&max_hops\=8\&limit\=100
+/// curl -v http://localhost:8080/paths\?from\=1\&to\=4\&selector\=region%3Deu
+/// ```
+#[utoipa::path(
+    get,
+    path = "/paths",
+    tag = "analysis",
+    params(PathsQuery, SelectorQuery),
+    responses(
+        (status = 200, description = "The shortest path and, with `all`, every simple path", body = Paths),
+        (status = 404, description = "No entity with the `from` or `to` id", body = ErrorResponse),
+        (status = 422, description = "`max_hops`, `limit` or selector not valid", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn find(
+    State(state): State<MyState>,
+    Query(query): Query<PathsQuery>,
+    selector: SelectorQuery,
+) -> Result<AppJson<Paths>, MyError> {
+    if !(1..=MAX_HOPS).contains(&query.max_hops) {
+        return Err(MyError::Validation(format!(
+            "max_hops must be between 1 and {MAX_HOPS}"
+        )));
+    }
+    if !(1..=MAX_PATHS).contains(&query.limit) {
+        return Err(MyError::Validation(format!(
+            "limit must be between 1 and {MAX_PATHS}"
+        )));
+    }
+
+    let graph = Graph::load(&state.db_state.pool()).await?;
+    let (Some(from), Some(to)) = (graph.index_of(query.from), graph.index_of(query.to)) else {
+        return Err(MyError::SqlxError(sqlx::Error::RowNotFound));
+    };
+
+    let through: Vec<bool> = graph
+        .entities
+        .iter()
+        .enumerate()
+        .map(|(node, entity)| {
+            node == from || node == to || selector.selector.matches(&entity.labels)
+        })
+        .collect();
+
+    // The search is CPU bound, keep it off the async workers
+    tokio::task::spawn_blocking(move || {
+        let shortest = graph
+            .shortest_path(from, to, &through)
+            .map(|path| hops(&graph, path));
+        let (paths, truncated) = if query.all {
+            let (paths, truncated) =
+                graph.simple_paths(from, to, &through, query.max_hops, query.limit, MAX_STEPS);
+            let paths = paths.into_iter().map(|path| hops(&graph, path)).collect();
+            (Some(paths), truncated)
+        } else {
+            (None, false)
+        };
+
+        Paths {
+            from: query.from,
+            to: query.to,
+            shortest,
+            paths,
+            truncated,
+        }
+    })
+    .await
+    .map(AppJson)
+    .map_err(|_| MyError::Message("Path search failed"))
+}
@@ -0,0 +1,314 @@
+use axum::http::StatusCode;
+use axum::{
+    Router,
+    extract::{Path, State},
+    response::IntoResponse,
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::{
+    MyState,
+    error::MyError,
+    webserver::{AppJson, DbBigSerial, entity_types},
+};
+
+/// Allowed pair of entity types on the ends of a relationship
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TypeRule {
+    pub from_type: String,
+    pub to_type: String,
+}
+
+/// A registered relationship type
+///
+/// When `rules` is empty the relationship may join entities of any type, otherwise the
+/// types of the `from` and `to` entities must match one of the rules.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RelationshipType {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub attributes_schema: Value,
+    #[serde(default)]
+    #[sqlx(json)]
+    pub rules: Vec<TypeRule>,
+}
+
+impl RelationshipType {
+    /// Check that a relationship from an entity of `from_type` to one of `to_type` is allowed
+    pub fn check_rules(&self, from_type: &str, to_type: &str) -> Result<(), MyError> {
+        if self.rules.is_empty()
+            || self
+                .rules
+                .iter()
+                .any(|rule| rule.from_type == from_type && rule.to_type == to_type)
+        {
+            return Ok(());
+        }
+
+        let allowed: Vec<String> = self
+            .rules
+            .iter()
+            .map(|rule| format!("{} -> {}", rule.from_type, rule.to_type))
+            .collect();
+        Err(MyError::Validation(format!(
+            "relationship type `{}` does not allow {from_type} -> {to_type}, allowed: {}",
+            self.name,
+            allowed.join(", ")
+        )))
+    }
+}
+
+const SELECT_RELATIONSHIP_TYPES: &str = r#"
+    SELECT t.name, t.description, t.attributes_schema,
+        COALESCE((
+            SELECT json_agg(json_build_object('from_type', r.from_type, 'to_type', r.to_type)
+                ORDER BY r.from_type, r.to_type)
+            FROM relationship_type_rules r
+            WHERE r.relationship_type = t.name
+        ), '[]') AS rules
+    FROM relationship_types t
+    "#;
+
+pub fn relationship_type_apis() -> Router<MyState> {
+    Router::new()
+        .route("/", post(create).get(list))
+        .route("/{name}", get(read).put(update).delete(delete))
+}
+
+async fn fetch(pool: &PgPool, name: &str) -> Result<Option<RelationshipType>, MyError> {
+    let relationship_type = sqlx::query_as::<_, RelationshipType>(&format!(
+        "{SELECT_RELATIONSHIP_TYPES} WHERE t.name = $1"
+    ))
+    .bind(name)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(relationship_type)
+}
+
+/// Validate a relationship against the registry before it is stored
+///
+/// The type must be registered, the entity types on each end must satisfy its rules and
+/// the attributes must match its schema.
+pub async fn validate_relationship(
+    pool: &PgPool,
+    relationship_type: &str,
+    from_id: DbBigSerial,
+    to_id: DbBigSerial,
+    attributes: &Value,
+) -> Result<(), MyError> {
+    let registered = fetch(pool, relationship_type).await?.ok_or_else(|| {
+        MyError::Validation(format!("unknown relationship type `{relationship_type}`"))
+    })?;
+
+    if !registered.rules.is_empty() {
+        let entity_type = |id: DbBigSerial| async move {
+            sqlx::query_scalar::<_, String>("SELECT type FROM entities WHERE id = $1")
+                .bind(id)
+                .fetch_optional(pool)
+                .await?
+                .ok_or_else(|| MyError::Validation(format!("unknown entity {id}")))
+        };
+        registered.check_rules(&entity_type(from_id).await?, &entity_type(to_id).await?)?;
+    }
+
+    entity_types::validate(&registered.attributes_schema, attributes)
+}
+
+async fn replace_rules(
+    tx: &mut Transaction<'_, Postgres>,
+    name: &str,
+    rules: &[TypeRule],
+) -> Result<(), MyError> {
+    sqlx::query("DELETE FROM relationship_type_rules WHERE relationship_type = $1")
+        .bind(name)
+        .execute(&mut **tx)
+        .await?;
+
+    let from_types: Vec<&str> = rules.iter().map(|rule| rule.from_type.as_str()).collect();
+    let to_types: Vec<&str> = rules.iter().map(|rule| rule.to_type.as_str()).collect();
+
+    sqlx::query(
+        r#"
+        INSERT INTO relationship_type_rules (relationship_type, from_type, to_type)
+        SELECT $1, from_type, to_type FROM UNNEST($2::VARCHAR[], $3::VARCHAR[]) AS rules(from_type, to_type)
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(name)
+    .bind(from_types)
+    .bind(to_types)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// List all relationship types with their rules and schemas
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -v http://localhost:8080/relationship-types
+/// ```
+async fn list(State(state): State<MyState>) -> Result<AppJson<Vec<RelationshipType>>, MyError> {
+    let types = sqlx::query_as::<_, RelationshipType>(&format!(
+        "{SELECT_RELATIONSHIP_TYPES} ORDER BY t.name"
+    ))
+    .fetch_all(&state.db_state.pool())
+    .await?;
+
+    Ok(AppJson(types))
+}
+
+/// Register a new relationship type
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -X POST http://localhost:8080/relationship-types \
+///      -H "Content-Type: application/json" \
+///      -d '{"name": "replicates_to", "attributes_schema": {"type": "object"}, "rules": [{"from_type": "database", "to_type": "database"}]}'
+/// ```
+async fn create(
+    State(state): State<MyState>,
+    AppJson(payload): AppJson<RelationshipType>,
+) -> Result<impl IntoResponse, MyError> {
+    entity_types::compile(&payload.attributes_schema)?;
+
+    let mut tx = state.db_state.pool().begin().await?;
+
+    sqlx::query(
+        "INSERT INTO relationship_types (name, description, attributes_schema) VALUES ($1, $2, $3)",
+    )
+    .bind(&payload.name)
+    .bind(&payload.description)
+    .bind(&payload.attributes_schema)
+    .execute(&mut *tx)
+    .await?;
+
+    replace_rules(&mut tx, &payload.name, &payload.rules).await?;
+
+    tx.commit().await?;
+
+    let relationship_type = fetch(&state.db_state.pool(), &payload.name)
+        .await?
+        .ok_or(MyError::Message("relationship type missing after insert"))?;
+
+    Ok((StatusCode::CREATED, AppJson(relationship_type)).into_response())
+}
+
+async fn read(
+    Path(name): Path<String>,
+    State(state): State<MyState>,
+) -> Result<AppJson<RelationshipType>, MyError> {
+    let relationship_type = fetch(&state.db_state.pool(), &name)
+        .await?
+        .ok_or(MyError::SqlxError(sqlx::Error::RowNotFound))?;
+
+    Ok(AppJson(relationship_type))
+}
+
+/// Update the description, schema and rules of a relationship type
+///
+/// Existing relationships are not revalidated, the new rules apply on their next update.
+async fn update(
+    State(state): State<MyState>,
+    Path(name): Path<String>,
+    AppJson(payload): AppJson<RelationshipType>,
+) -> Result<impl IntoResponse, MyError> {
+    if name != payload.name {
+        return Err(MyError::Validation(
+            "names on path and body must match for update".into(),
+        ));
+    }
+
+    entity_types::compile(&payload.attributes_schema)?;
+
+    let mut tx = state.db_state.pool().begin().await?;
+
+    let updated = sqlx::query(
+        "UPDATE relationship_types SET description = $2, attributes_schema = $3 WHERE name = $1",
+    )
+    .bind(&name)
+    .bind(&payload.description)
+    .bind(&payload.attributes_schema)
+    .execute(&mut *tx)
+    .await?;
+
+    if updated.rows_affected() == 0 {
+        return Err(MyError::SqlxError(sqlx::Error::RowNotFound));
+    }
+
+    replace_rules(&mut tx, &name, &payload.rules).await?;
+
+    tx.commit().await?;
+
+    let relationship_type = fetch(&state.db_state.pool(), &name)
+        .await?
+        .ok_or(MyError::SqlxError(sqlx::Error::RowNotFound))?;
+
+    Ok(AppJson(relationship_type))
+}
+
+/// Delete a relationship type, refused while any relationship still uses it
+async fn delete(
+    State(state): State<MyState>,
+    Path(name): Path<String>,
+) -> Result<AppJson<RelationshipType>, MyError> {
+    let in_use = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM relationships WHERE relationship_type = $1",
+    )
+    .bind(&name)
+    .fetch_one(&state.db_state.pool())
+    .await?;
+
+    if in_use > 0 {
+        return Err(MyError::Validation(format!(
+            "relationship type `{name}` is used by {in_use} relationships"
+        )));
+    }
+
+    let relationship_type = fetch(&state.db_state.pool(), &name)
+        .await?
+        .ok_or(MyError::SqlxError(sqlx::Error::RowNotFound))?;
+
+    sqlx::query("DELETE FROM relationship_types WHERE name = $1")
+        .bind(&name)
+        .execute(&state.db_state.pool())
+        .await?;
+
+    Ok(AppJson(relationship_type))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn rules_restrict_entity_types() {
+        let mut reads_from = RelationshipType {
+            name: "reads_from".into(),
+            description: None,
+            attributes_schema: json!({"type": "object"}),
+            rules: vec![],
+        };
+        assert!(reads_from.check_rules("vm", "network").is_ok());
+
+        reads_from.rules.push(TypeRule {
+            from_type: "service".into(),
+            to_type: "database".into(),
+        });
+        assert!(reads_from.check_rules("service", "database").is_ok());
+        assert!(matches!(
+            reads_from.check_rules("database", "service"),
+            Err(MyError::Validation(_))
+        ));
+    }
+}
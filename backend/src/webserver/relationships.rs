@@ -7,23 +7,34 @@ use axum::{
     routing::{get, post},
 };
 use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, types::Json};
+use utoipa::{OpenApi, ToSchema};
 
+use crate::webserver::labels::{Labels, SelectorQuery, validate_labels};
+use crate::webserver::relationship_types::validate_relationship;
 use crate::webserver::{ListPages, PageOptions};
 use crate::{
     MyState,
     error::MyError,
-    webserver::{AppJson, DbBigSerial},
+    webserver::{AppJson, DbBigSerial, ErrorResponse},
 };
 
-#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
 pub struct Relationship {
+    #[schema(value_type = Option<i64>)]
     pub id: Option<DbBigSerial>,
+    #[schema(value_type = i64)]
     pub from_id: DbBigSerial,
+    #[schema(value_type = i64)]
     pub to_id: DbBigSerial,
     // Using string for flexible relationship types: 'depends_on', 'hosted_on', etc.
     pub relationship_type: String,
     // JSONB attributes as per plan
     pub attributes: serde_json::Value,
+    #[serde(default)]
+    #[sqlx(json)]
+    #[schema(value_type = BTreeMap<String, String>)]
+    pub labels: Labels,
 }
 
 pub fn relationship_apis() -> Router<MyState> {
@@ -32,47 +43,103 @@ pub fn relationship_apis() -> Router<MyState> {
         .route("/{id}", get(read).put(update).delete(delete))
 }
 
+#[derive(OpenApi)]
+#[openapi(paths(list, create, read, update, delete))]
+pub(crate) struct RelationshipApi;
+
+/// List relationship ids, optionally restricted by a label selector
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -v http://localhost:8080/relationships\?selector\=env%3Dprod
+/// ```
+#[utoipa::path(
+    get,
+    path = "/relationships",
+    tag = "relationships",
+    params(PageOptions, SelectorQuery),
+    responses(
+        (status = 200, description = "A page of relationship ids", body = ListPages),
+        (status = 422, description = "Malformed selector", body = ErrorResponse),
+    )
+)]
 async fn list(
     State(state): State<MyState>,
     Query(options): Query<PageOptions>,
+    selector: SelectorQuery,
 ) -> Result<AppJson<ListPages>, MyError> {
     let options = PageOptions::defaulting(options);
 
-    let items = sqlx::query_as::<_, Relationship>(
-        r#"SELECT id, from_id, to_id, relationship_type, attributes FROM relationships
-        LIMIT $1 OFFSET $2
-        "#,
-    )
-    .bind(options.size)
-    .bind(options.page.unwrap() * options.size.unwrap())
-    .fetch_all(&state.db_state.pool_pg)
-    .await?;
+    let mut query = QueryBuilder::new("SELECT id FROM relationships WHERE TRUE");
+    selector.selector.push_sql(&mut query, "labels");
+    query
+        .push(" ORDER BY id LIMIT ")
+        .push_bind(options.size)
+        .push(" OFFSET ")
+        .push_bind(options.page.unwrap() * options.size.unwrap());
+
+    let ids = query
+        .build_query_scalar::<DbBigSerial>()
+        .fetch_all(&state.db_state.pool())
+        .await?;
 
     let list_ids = ListPages {
         pagination: options,
-        ids: items.iter().map(|u| u.id.unwrap()).collect(),
+        ids,
     };
 
     Ok(AppJson(list_ids))
 }
 
+#[utoipa::path(
+    post,
+    path = "/relationships",
+    tag = "relationships",
+    request_body = Relationship,
+    responses(
+        (status = 201, description = "Relationship created", body = Relationship),
+        (status = 422, description = "Type not allowed between the entities, or attributes or labels not valid", body = ErrorResponse),
+    )
+)]
 async fn create(
     State(state): State<MyState>,
     AppJson(payload): AppJson<Relationship>,
 ) -> Result<impl IntoResponse, MyError> {
+    validate_relationship(
+        &state.db_state.pool(),
+        &payload.relationship_type,
+        payload.from_id,
+        payload.to_id,
+        &payload.attributes,
+    )
+    .await?;
+    validate_labels(&payload.labels)?;
+
     let relationship = sqlx::query_as::<_, Relationship>(
-        "INSERT INTO relationships (from_id, to_id, relationship_type, attributes) VALUES ($1, $2, $3, $4) RETURNING *",
+        "INSERT INTO relationships (from_id, to_id, relationship_type, attributes, labels) VALUES ($1, $2, $3, $4, $5) RETURNING *",
     )
     .bind(payload.from_id)
     .bind(payload.to_id)
     .bind(payload.relationship_type)
     .bind(payload.attributes)
-    .fetch_one(&state.db_state.pool_pg)
+    .bind(Json(payload.labels))
+    .fetch_one(&state.db_state.pool())
     .await?;
 
     Ok((StatusCode::CREATED, AppJson(relationship)).into_response())
 }
 
+#[utoipa::path(
+    get,
+    path = "/relationships/{id}",
+    tag = "relationships",
+    params(("id" = i64, Path, description = "Relationship id")),
+    responses(
+        (status = 200, description = "The relationship", body = Relationship),
+        (status = 404, description = "No relationship with the id", body = ErrorResponse),
+    )
+)]
 async fn read(
     Path(id): Path<DbBigSerial>,
     State(state): State<MyState>,
@@ -80,27 +147,49 @@ async fn read(
     let relationship =
         sqlx::query_as::<_, Relationship>("SELECT * FROM relationships WHERE id = $1")
             .bind(id)
-            .fetch_one(&state.db_state.pool_pg)
+            .fetch_one(&state.db_state.pool())
             .await?;
 
     Ok(AppJson(relationship))
 }
 
+#[utoipa::path(
+    put,
+    path = "/relationships/{id}",
+    tag = "relationships",
+    params(("id" = i64, Path, description = "Relationship id, matching the id in the body")),
+    request_body = Relationship,
+    responses(
+        (status = 200, description = "The updated relationship", body = Relationship),
+        (status = 404, description = "No relationship with the id", body = ErrorResponse),
+        (status = 422, description = "Path and body ids differ, type not allowed between the entities, or attributes or labels not valid", body = ErrorResponse),
+    )
+)]
 async fn update(
     State(state): State<MyState>,
     Path(id): Path<DbBigSerial>,
     AppJson(payload): AppJson<Relationship>,
 ) -> Result<impl IntoResponse, MyError> {
     if payload.id.is_none() || id != payload.id.unwrap() {
-        return Err(MyError::Message(
-            "ids on path and body must match for update",
+        return Err(MyError::Validation(
+            "ids on path and body must match for update".into(),
         ));
     }
 
+    validate_relationship(
+        &state.db_state.pool(),
+        &payload.relationship_type,
+        payload.from_id,
+        payload.to_id,
+        &payload.attributes,
+    )
+    .await?;
+    validate_labels(&payload.labels)?;
+
     let relationship = sqlx::query_as::<_, Relationship>(
         r#"
         UPDATE relationships
-        SET from_id = $2, to_id = $3, relationship_type = $4, attributes = $5
+        SET from_id = $2, to_id = $3, relationship_type = $4, attributes = $5, labels = $6
         WHERE id = $1
         RETURNING *
         "#,
@@ -110,12 +199,23 @@ async fn update(
     .bind(payload.to_id)
     .bind(payload.relationship_type)
     .bind(payload.attributes)
-    .fetch_one(&state.db_state.pool_pg)
+    .bind(Json(payload.labels))
+    .fetch_one(&state.db_state.pool())
     .await?;
 
     Ok(AppJson(relationship))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/relationships/{id}",
+    tag = "relationships",
+    params(("id" = i64, Path, description = "Relationship id")),
+    responses(
+        (status = 200, description = "The deleted relationship", body = Relationship),
+        (status = 404, description = "No relationship with the id", body = ErrorResponse),
+    )
+)]
 async fn delete(
     State(state): State<MyState>,
     Path(id): Path<DbBigSerial>,
@@ -123,7 +223,7 @@ async fn delete(
     let relationship =
         sqlx::query_as::<_, Relationship>("DELETE FROM relationships WHERE id = $1 RETURNING *")
             .bind(id)
-            .fetch_one(&state.db_state.pool_pg)
+            .fetch_one(&state.db_state.pool())
             .await?;
 
     Ok(AppJson(relationship))
@@ -0,0 +1,118 @@
+use axum::{
+    Router,
+    extract::{Query, State},
+    routing::get,
+};
+use serde::Serialize;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::{
+    MyState,
+    error::MyError,
+    graph::Graph,
+    risk::{self, EntityRisk, Factor},
+    webserver::{AppJson, ErrorResponse, PageOptions, SortOrder},
+};
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct RiskRanking {
+    /// Entities scored, over all pages
+    pub total: usize,
+    pub entities: Vec<EntityRisk>,
+    pub pagination: PageOptions,
+}
+
+#[derive(OpenApi)]
+#[openapi(paths(ranking))]
+pub(crate) struct RiskApi;
+
+pub fn risk_apis() -> Router<MyState> {
+    Router::new().route("/", get(ranking))
+}
+
+enum SortKey {
+    Score,
+    Name,
+    Factor(Factor),
+}
+
+/// Order `risks` by `property`: `score`, the contribution of a factor, or `name`
+fn sort(risks: &mut [EntityRisk], property: &str, direction: &SortOrder) -> Result<(), MyError> {
+    let key = match property {
+        "score" => SortKey::Score,
+        "name" => SortKey::Name,
+        factor => SortKey::Factor(serde_json::from_value(factor.into()).map_err(|_| {
+            MyError::Validation(format!(
+                "cannot sort by {factor}, use score, name, fan_in, availability, latency, redundancy or owner"
+            ))
+        })?),
+    };
+
+    risks.sort_by(|a, b| {
+        let (first, second) = match direction {
+            SortOrder::Asc => (a, b),
+            SortOrder::Desc => (b, a),
+        };
+        let order = match key {
+            SortKey::Score => first.score.total_cmp(&second.score),
+            SortKey::Name => first.entity.name.cmp(&second.entity.name),
+            SortKey::Factor(factor) => first
+                .contribution(factor)
+                .total_cmp(&second.contribution(factor)),
+        };
+        order.then(a.entity.id.cmp(&b.entity.id))
+    });
+    Ok(())
+}
+
+/// Entities ranked by risk score, each factor with its weight, contribution and explanation
+///
+/// Sorted by `score` descending unless `property` (`score`, `name` or a factor: `fan_in`,
+/// `availability`, `latency`, `redundancy`, `owner`) is given, with `direction` `asc` or
+/// `desc` (the default). Weights are set in the `risk` config.
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -v http://localhost:8080/risk\?size\=20
+/// curl -v http://localhost:8080/risk\?property\=name\&direction\=asc
+/// ```
+#[utoipa::path(
+    get,
+    path = "/risk",
+    tag = "analysis",
+    params(
+        PageOptions,
+        ("property" = Option<String>, Query, description = "`score`, `name`, `fan_in`, `availability`, `latency`, `redundancy` or `owner`, default `score`"),
+        ("direction" = Option<SortOrder>, Query, description = "`asc` or `desc`, default `desc`"),
+    ),
+    responses(
+        (status = 200, description = "A page of entities with their risk score and its factors", body = RiskRanking),
+        (status = 422, description = "Unknown sort property", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn ranking(
+    State(state): State<MyState>,
+    Query(options): Query<PageOptions>,
+) -> Result<AppJson<RiskRanking>, MyError> {
+    let options = PageOptions::defaulting(options);
+    let size = options.size.unwrap().max(0) as usize;
+    let page = options.page.unwrap().max(0) as usize;
+
+    let graph = Graph::load(&state.db_state.pool()).await?;
+    let mut risks = risk::score(&graph, &state.live.risk());
+    match &options.sort {
+        Some(sort_by) => sort(&mut risks, &sort_by.property, &sort_by.direction)?,
+        None => sort(&mut risks, "score", &SortOrder::Desc)?,
+    }
+
+    Ok(AppJson(RiskRanking {
+        total: risks.len(),
+        entities: risks
+            .into_iter()
+            .skip(page.saturating_mul(size))
+            .take(size)
+            .collect(),
+        pagination: options,
+    }))
+}
@@ -0,0 +1,26 @@
+use axum::{Router, extract::State, routing::post};
+
+use crate::{
+    MyState,
+    error::MyError,
+    scraper::{ScrapeReport, client, scrape},
+    webserver::AppJson,
+};
+
+pub fn scraper_apis() -> Router<MyState> {
+    Router::new().route("/", post(scrape_now))
+}
+
+/// Scrape every entity's Prometheus target now rather than waiting for the next interval
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -X POST http://localhost:8080/scrape
+/// ```
+async fn scrape_now(State(state): State<MyState>) -> Result<AppJson<ScrapeReport>, MyError> {
+    let config = &state.config.scraper;
+    let report = scrape(&state.db_state.pool(), &client(config)?, config).await?;
+
+    Ok(AppJson(report))
+}
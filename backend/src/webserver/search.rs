@@ -0,0 +1,115 @@
+use axum::{
+    Router,
+    extract::{Query, State},
+    routing::get,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    MyState,
+    error::MyError,
+    webserver::{AppJson, DbBigSerial, PageOptions},
+};
+
+#[derive(Deserialize, Debug)]
+pub struct SearchQuery {
+    pub q: String,
+}
+
+#[derive(Serialize, Debug, sqlx::FromRow)]
+pub struct SearchHit {
+    pub id: DbBigSerial,
+    pub name: String,
+    #[sqlx(rename = "type")]
+    #[serde(rename = "type")]
+    pub entity_type: String,
+    /// Full-text rank plus trigram word similarity, higher is better
+    pub score: f64,
+    /// Fragments of the name, type and attribute values with matches wrapped in `<mark>`
+    pub highlight: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SearchResults {
+    pub query: String,
+    pub hits: Vec<SearchHit>,
+    pub pagination: PageOptions,
+}
+
+pub fn search_apis() -> Router<MyState> {
+    Router::new().route("/", get(search))
+}
+
+/// Build a prefix matching `tsquery` requiring every word of the search text
+fn prefix_query(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("{}:*", word.to_lowercase()))
+        .collect::<Vec<_>>()
+        .join(" & ")
+}
+
+/// Search entities by name, type and string attribute values
+///
+/// Words are matched as prefixes by full-text search, misspellings are caught by trigram
+/// word similarity. Results are ranked by the sum of both scores.
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -v http://localhost:8080/search\?q\=payments\&size\=10
+/// ```
+async fn search(
+    State(state): State<MyState>,
+    Query(search): Query<SearchQuery>,
+    Query(options): Query<PageOptions>,
+) -> Result<AppJson<SearchResults>, MyError> {
+    let options = PageOptions::defaulting(options);
+
+    let text = search.q.trim();
+    let tsquery = prefix_query(text);
+    if tsquery.is_empty() {
+        return Err(MyError::Validation(
+            "search query must contain at least one word".into(),
+        ));
+    }
+
+    let hits = sqlx::query_as::<_, SearchHit>(
+        r#"
+        SELECT e.id, e.name, e.type,
+            (ts_rank(entity_search_document(e.name, e.type, e.attributes), q.query)
+                + word_similarity($1, entity_search_text(e.name, e.type, e.attributes)))::FLOAT8 AS score,
+            ts_headline('simple', entity_search_text(e.name, e.type, e.attributes), q.query,
+                'StartSel=<mark>, StopSel=</mark>, MaxFragments=2, MinWords=3, MaxWords=12') AS highlight
+        FROM entities e, to_tsquery('simple', $2) AS q(query)
+        WHERE entity_search_document(e.name, e.type, e.attributes) @@ q.query
+            OR $1 <% entity_search_text(e.name, e.type, e.attributes)
+        ORDER BY score DESC, e.name
+        LIMIT $3 OFFSET $4
+        "#,
+    )
+    .bind(text)
+    .bind(tsquery)
+    .bind(options.size)
+    .bind(options.page.unwrap() * options.size.unwrap())
+    .fetch_all(&state.db_state.pool())
+    .await?;
+
+    Ok(AppJson(SearchResults {
+        query: text.to_owned(),
+        hits,
+        pagination: options,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn prefix_query_strips_operators() {
+        assert_eq!(prefix_query("Payments API"), "payments:* & api:*");
+        assert_eq!(prefix_query("a|b & !c"), "a:* & b:* & c:*");
+        assert_eq!(prefix_query(" ':* "), "");
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+
+use axum::{Router, extract::State, routing::post};
+use serde::{Deserialize, Serialize};
+use utoipa::{OpenApi, ToSchema};
+
+use crate::{
+    MyState,
+    error::MyError,
+    graph::{Graph, simulation::Figures},
+    webserver::{AppJson, DbBigSerial, ErrorResponse, entities::EntitySummary},
+};
+
+/// A failure or degradation of one entity to simulate
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct EntityChange {
+    #[schema(value_type = i64)]
+    pub id: DbBigSerial,
+    /// The entity is down, its availability 0
+    #[serde(default)]
+    pub failed: bool,
+    /// Availability percentage replacing the declared one
+    #[serde(default)]
+    pub availability: Option<f64>,
+    /// Milliseconds added to the declared `p95_millis` and `p99_millis`
+    #[serde(default)]
+    pub added_latency_millis: f64,
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct SimulationRequest {
+    pub changes: Vec<EntityChange>,
+}
+
+/// Availability as a percentage and latencies in milliseconds
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, ToSchema)]
+pub struct Slis {
+    pub availability: f64,
+    pub p95_millis: f64,
+    pub p99_millis: f64,
+}
+
+impl From<Figures> for Slis {
+    fn from(figures: Figures) -> Self {
+        Slis {
+            availability: figures.availability * 100.0,
+            p95_millis: figures.p95_millis,
+            p99_millis: figures.p99_millis,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct SimulatedEntity {
+    #[serde(flatten)]
+    pub entity: EntitySummary,
+    /// Named in the request rather than affected through its dependencies
+    pub changed: bool,
+    /// Simulated availability is 0
+    pub down: bool,
+    pub declared: Slis,
+    pub simulated: Slis,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct SimulationResult {
+    /// Entities whose availability or latency changed, largest availability loss first
+    pub affected: Vec<SimulatedEntity>,
+}
+
+#[derive(OpenApi)]
+#[openapi(paths(simulate))]
+pub(crate) struct SimulateApi;
+
+pub fn simulate_apis() -> Router<MyState> {
+    Router::new().route("/", post(simulate))
+}
+
+/// The figures of the changed entities, by position in `graph`
+fn changed_figures(
+    graph: &Graph,
+    changes: &[EntityChange],
+) -> Result<HashMap<usize, Figures>, MyError> {
+    if changes.is_empty() {
+        return Err(MyError::Validation(
+            "changes must name at least one entity".into(),
+        ));
+    }
+
+    let declared = graph.declared_figures();
+    let mut changed = HashMap::new();
+    for change in changes {
+        let node = graph
+            .index_of(change.id)
+            .ok_or_else(|| MyError::Validation(format!("no entity with id {}", change.id)))?;
+        if let Some(availability) = change.availability
+            && !(0.0..=100.0).contains(&availability)
+        {
+            return Err(MyError::Validation(format!(
+                "availability of entity {} must be between 0 and 100",
+                change.id
+            )));
+        }
+        if change.added_latency_millis < 0.0 {
+            return Err(MyError::Validation(format!(
+                "added_latency_millis of entity {} must not be negative",
+                change.id
+            )));
+        }
+
+        let mut figures = declared[node];
+        if let Some(availability) = change.availability {
+            figures.availability = availability / 100.0;
+        }
+        if change.failed {
+            figures.availability = 0.0;
+        }
+        figures.p95_millis += change.added_latency_millis;
+        figures.p99_millis += change.added_latency_millis;
+        changed.insert(node, figures);
+    }
+    Ok(changed)
+}
+
+/// Simulate failing or degrading entities and report the effect on their consumers
+///
+/// Nothing is stored. Consumers' declared figures move by the change in what their
+/// dependencies provide: dependencies of the same type are redundant, of different types all
+/// needed, so a consumer is down once every dependency of one type is.
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -X POST http://localhost:8080/simulate \
+///      -H "Content-Type: application/json" \
+///      -d '{"changes": [{"id": 3, "failed": true}, {"id": 5, "availability": 99.0, "added_latency_millis": 50}]}'
+/// ```
+#[utoipa::path(
+    post,
+    path = "/simulate",
+    tag = "analysis",
+    request_body = SimulationRequest,
+    responses(
+        (status = 200, description = "The changed and affected entities, largest availability loss first", body = SimulationResult),
+        (status = 422, description = "No changes, an unknown entity or a change out of range", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn simulate(
+    State(state): State<MyState>,
+    AppJson(request): AppJson<SimulationRequest>,
+) -> Result<AppJson<SimulationResult>, MyError> {
+    let graph = Graph::load(&state.db_state.pool()).await?;
+    let changed = changed_figures(&graph, &request.changes)?;
+
+    // Strongly connected components are settled by repeated passes, keep them off the async
+    // workers
+    tokio::task::spawn_blocking(move || affected(&graph, &changed))
+        .await
+        .map(|affected| AppJson(SimulationResult { affected }))
+        .map_err(|_| MyError::Message("Simulation failed"))
+}
+
+/// Entities whose figures differ from those declared once `changed` are applied
+fn affected(graph: &Graph, changed: &HashMap<usize, Figures>) -> Vec<SimulatedEntity> {
+    let declared = graph.declared_figures();
+    let simulated = graph.simulate(changed);
+
+    let mut affected: Vec<SimulatedEntity> = (0..graph.len())
+        .filter(|&node| changed.contains_key(&node) || simulated[node] != declared[node])
+        .map(|node| SimulatedEntity {
+            entity: EntitySummary::from(&graph.entities[node]),
+            changed: changed.contains_key(&node),
+            down: simulated[node].availability == 0.0,
+            declared: declared[node].into(),
+            simulated: simulated[node].into(),
+        })
+        .collect();
+
+    let loss = |entity: &SimulatedEntity| {
+        (
+            entity.declared.availability - entity.simulated.availability,
+            entity.simulated.p99_millis - entity.declared.p99_millis,
+        )
+    };
+    affected.sort_by(|a, b| {
+        loss(b)
+            .partial_cmp(&loss(a))
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.entity.id.cmp(&b.entity.id))
+    });
+    affected
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+    use crate::webserver::{entities::Entity, relationships::Relationship};
+
+    fn entity(id: i64) -> Entity {
+        serde_json::from_value(json!({
+            "id": id, "name": format!("e{id}"), "type": "service",
+            "p99_millis": 100, "p95_millis": 50, "availability": 99.9, "throughput_rps": 0,
+            "attributes": {},
+        }))
+        .unwrap()
+    }
+
+    fn change(value: serde_json::Value) -> EntityChange {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn changes_are_validated() {
+        let relationship: Relationship = serde_json::from_value(json!({
+            "id": 1, "from_id": 1, "to_id": 2,
+            "relationship_type": "depends_on", "attributes": {},
+        }))
+        .unwrap();
+        let graph = Graph::new(vec![entity(1), entity(2)], vec![relationship]);
+        let invalid = |changes: &[EntityChange]| {
+            matches!(
+                changed_figures(&graph, changes),
+                Err(MyError::Validation(_))
+            )
+        };
+
+        assert!(invalid(&[]));
+        assert!(invalid(&[change(json!({"id": 3, "failed": true}))]));
+        assert!(invalid(&[change(json!({"id": 2, "availability": 100.5}))]));
+        assert!(invalid(&[change(json!({"id": 2, "availability": -1.0}))]));
+        assert!(invalid(&[change(
+            json!({"id": 2, "added_latency_millis": -5.0})
+        )]));
+
+        let changed = changed_figures(
+            &graph,
+            &[change(
+                json!({"id": 2, "availability": 99.0, "added_latency_millis": 20.0}),
+            )],
+        )
+        .unwrap();
+        let figures = changed[&graph.index_of(2).unwrap()];
+        assert!((figures.availability - 0.99).abs() < 1e-9);
+        assert_eq!(figures.p99_millis, 120.0);
+
+        let affected = affected(&graph, &changed);
+        assert_eq!(affected.len(), 2);
+        assert!(
+            affected
+                .iter()
+                .all(|entity| entity.simulated.p99_millis > 100.0)
+        );
+    }
+}
@@ -0,0 +1,354 @@
+use axum::http::StatusCode;
+use axum::{
+    Router,
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    routing::{get, post},
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, QueryBuilder};
+use std::collections::HashMap;
+
+use crate::{
+    MyState,
+    error::MyError,
+    slo::{
+        Measurement, Objective, WINDOW_DAYS, WindowBudget,
+        drift::{DriftStatus, DriftThresholds, EntityDrift, drift},
+        window_budget,
+    },
+    webserver::{AppJson, DbBigSerial, PageOptions, labels::SelectorQuery},
+};
+
+#[derive(Deserialize, Debug)]
+pub struct MeasurementQuery {
+    pub entity_id: Option<DbBigSerial>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Ingested {
+    pub inserted: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ErrorBudget {
+    pub entity_id: DbBigSerial,
+    pub objective: Objective,
+    pub windows: Vec<WindowBudget>,
+}
+
+fn default_drift_days() -> i64 {
+    28
+}
+
+/// Longest drift window, a year of measurements
+const MAX_DRIFT_DAYS: i64 = 365;
+
+#[derive(Deserialize, Debug)]
+pub struct DriftQuery {
+    /// Days of measurements to compare against the declared values
+    #[serde(default = "default_drift_days")]
+    pub days: i64,
+    /// Include entities meeting their declarations or without data
+    #[serde(default)]
+    pub all: bool,
+}
+
+#[derive(sqlx::FromRow)]
+struct Declared {
+    id: DbBigSerial,
+    name: String,
+    #[sqlx(flatten)]
+    objective: Objective,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DriftEntry {
+    pub entity_id: DbBigSerial,
+    pub name: String,
+    #[serde(flatten)]
+    pub drift: EntityDrift,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DriftReport {
+    pub days: i64,
+    pub entities: Vec<DriftEntry>,
+}
+
+pub fn sli_apis() -> Router<MyState> {
+    Router::new()
+        .route("/", post(ingest).get(list))
+        .route("/drift", get(drift_report))
+        .route("/{entity_id}", post(ingest_entity))
+        .route("/{entity_id}/budget", get(budget))
+}
+
+/// Store measurements, each of which must name its entity
+pub async fn insert_measurements(
+    pool: &PgPool,
+    measurements: &[Measurement],
+) -> Result<u64, MyError> {
+    for measurement in measurements {
+        if measurement.entity_id.is_none() {
+            return Err(MyError::Validation(
+                "measurements must set entity_id".into(),
+            ));
+        }
+        if measurement.is_empty() {
+            return Err(MyError::Validation(
+                "measurements must set at least one SLI".into(),
+            ));
+        }
+        if measurement
+            .availability
+            .is_some_and(|a| !(0.0..=100.0).contains(&a))
+        {
+            return Err(MyError::Validation(
+                "availability is a percentage between 0 and 100".into(),
+            ));
+        }
+    }
+
+    let inserted = sqlx::query(
+        r#"
+        INSERT INTO sli_measurements (entity_id, measured_at, availability, p95_millis, p99_millis, throughput_rps, source)
+        SELECT * FROM UNNEST($1::BIGINT[], $2::TIMESTAMPTZ[], $3::FLOAT8[], $4::INTEGER[], $5::INTEGER[], $6::INTEGER[], $7::VARCHAR[])
+        "#,
+    )
+    .bind(measurements.iter().map(|m| m.entity_id).collect::<Vec<_>>())
+    .bind(measurements.iter().map(|m| m.measured_at).collect::<Vec<_>>())
+    .bind(measurements.iter().map(|m| m.availability).collect::<Vec<_>>())
+    .bind(measurements.iter().map(|m| m.p95_millis).collect::<Vec<_>>())
+    .bind(measurements.iter().map(|m| m.p99_millis).collect::<Vec<_>>())
+    .bind(measurements.iter().map(|m| m.throughput_rps).collect::<Vec<_>>())
+    .bind(measurements.iter().map(|m| m.source.as_str()).collect::<Vec<_>>())
+    .execute(pool)
+    .await?;
+
+    Ok(inserted.rows_affected())
+}
+
+/// Ingest a batch of measurements for any entities
+///
+/// `measured_at` defaults to now and `source` to `manual`, unmeasured SLIs may be omitted.
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -X POST http://localhost:8080/slis \
+///      -H "Content-Type: application/json" \
+///      -d '[{"entity_id": 1, "availability": 99.95, "p95_millis": 80, "p99_millis": 150}]'
+/// ```
+async fn ingest(
+    State(state): State<MyState>,
+    AppJson(measurements): AppJson<Vec<Measurement>>,
+) -> Result<impl IntoResponse, MyError> {
+    let inserted = insert_measurements(&state.db_state.pool(), &measurements).await?;
+
+    Ok((StatusCode::CREATED, AppJson(Ingested { inserted })).into_response())
+}
+
+/// Ingest a batch of measurements for one entity
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -X POST http://localhost:8080/slis/1 \
+///      -H "Content-Type: application/json" \
+///      -d '[{"measured_at": "2026-10-19T12:00:00Z", "availability": 99.9, "throughput_rps": 40}]'
+/// ```
+async fn ingest_entity(
+    State(state): State<MyState>,
+    Path(entity_id): Path<DbBigSerial>,
+    AppJson(mut measurements): AppJson<Vec<Measurement>>,
+) -> Result<impl IntoResponse, MyError> {
+    for measurement in &mut measurements {
+        if measurement.entity_id.is_some_and(|id| id != entity_id) {
+            return Err(MyError::Validation(
+                "ids on path and body must match for ingest".into(),
+            ));
+        }
+        measurement.entity_id = Some(entity_id);
+    }
+
+    let inserted = insert_measurements(&state.db_state.pool(), &measurements).await?;
+
+    Ok((StatusCode::CREATED, AppJson(Ingested { inserted })).into_response())
+}
+
+/// List measurements, newest first
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -v http://localhost:8080/slis\?entity_id\=1\&since\=2026-10-01T00:00:00Z\&size\=100
+/// ```
+async fn list(
+    State(state): State<MyState>,
+    Query(filter): Query<MeasurementQuery>,
+    Query(options): Query<PageOptions>,
+) -> Result<AppJson<Vec<Measurement>>, MyError> {
+    let options = PageOptions::defaulting(options);
+
+    let mut query = QueryBuilder::new("SELECT * FROM sli_measurements WHERE TRUE");
+    if let Some(entity_id) = filter.entity_id {
+        query.push(" AND entity_id = ").push_bind(entity_id);
+    }
+    if let Some(since) = filter.since {
+        query.push(" AND measured_at >= ").push_bind(since);
+    }
+    if let Some(until) = filter.until {
+        query.push(" AND measured_at < ").push_bind(until);
+    }
+    query
+        .push(" ORDER BY measured_at DESC, id DESC LIMIT ")
+        .push_bind(options.size)
+        .push(" OFFSET ")
+        .push_bind(options.page.unwrap() * options.size.unwrap());
+
+    let measurements = query
+        .build_query_as::<Measurement>()
+        .fetch_all(&state.db_state.pool())
+        .await?;
+
+    Ok(AppJson(measurements))
+}
+
+/// Remaining error budget and burn rate over rolling 7, 28 and 30 day windows
+///
+/// Measured SLIs are compared against the entity's declared `availability`, `p95_millis`
+/// and `p99_millis`.
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -v http://localhost:8080/slis/1/budget
+/// ```
+async fn budget(
+    State(state): State<MyState>,
+    Path(entity_id): Path<DbBigSerial>,
+) -> Result<AppJson<ErrorBudget>, MyError> {
+    let pool = &state.db_state.pool();
+
+    let objective = sqlx::query_as::<_, Objective>(
+        "SELECT availability, p95_millis, p99_millis, throughput_rps FROM entities WHERE id = $1",
+    )
+    .bind(entity_id)
+    .fetch_one(pool)
+    .await?;
+
+    let now = Utc::now();
+    let longest = WINDOW_DAYS.iter().max().copied().unwrap_or_default();
+
+    let measurements = sqlx::query_as::<_, Measurement>(
+        "SELECT * FROM sli_measurements WHERE entity_id = $1 AND measured_at > $2 AND measured_at <= $3",
+    )
+    .bind(entity_id)
+    .bind(now - Duration::days(longest))
+    .bind(now)
+    .fetch_all(pool)
+    .await?;
+
+    let windows = WINDOW_DAYS
+        .iter()
+        .map(|&days| window_budget(&objective, &measurements, now, days))
+        .collect();
+
+    Ok(AppJson(ErrorBudget {
+        entity_id,
+        objective,
+        windows,
+    }))
+}
+
+/// Compare declared SLIs against the measured ones, worst offenders first
+///
+/// Only entities consistently breaching (`breach_ratio` of samples worse than declared) or
+/// massively over-promising (mean shortfall of `factor` or more) are listed unless `all` is
+/// set. See `crate::slo::drift` for how each metric's shortfall is measured.
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -v http://localhost:8080/slis/drift\?days\=28\&factor\=3\&selector\=tier%3Dcritical
+/// ```
+async fn drift_report(
+    State(state): State<MyState>,
+    Query(query): Query<DriftQuery>,
+    Query(thresholds): Query<DriftThresholds>,
+    selector: SelectorQuery,
+) -> Result<AppJson<DriftReport>, MyError> {
+    if !(1..=MAX_DRIFT_DAYS).contains(&query.days) {
+        return Err(MyError::Validation(format!(
+            "days must be between 1 and {MAX_DRIFT_DAYS}"
+        )));
+    }
+    thresholds.validate()?;
+
+    let pool = &state.db_state.pool();
+
+    let mut declared_query = QueryBuilder::new(
+        "SELECT id, name, availability, p95_millis, p99_millis, throughput_rps FROM entities WHERE TRUE",
+    );
+    selector.selector.push_sql(&mut declared_query, "labels");
+    let declared = declared_query
+        .push(" ORDER BY id")
+        .build_query_as::<Declared>()
+        .fetch_all(pool)
+        .await?;
+
+    let ids: Vec<DbBigSerial> = declared.iter().map(|d| d.id).collect();
+    let measurements = sqlx::query_as::<_, Measurement>(
+        "SELECT * FROM sli_measurements WHERE entity_id = ANY($1) AND measured_at > $2",
+    )
+    .bind(ids)
+    .bind(Utc::now() - Duration::days(query.days))
+    .fetch_all(pool)
+    .await?;
+
+    let mut by_entity: HashMap<DbBigSerial, Vec<Measurement>> = HashMap::new();
+    for measurement in measurements {
+        if let Some(entity_id) = measurement.entity_id {
+            by_entity.entry(entity_id).or_default().push(measurement);
+        }
+    }
+
+    let mut entities: Vec<DriftEntry> = declared
+        .into_iter()
+        .map(|declared| DriftEntry {
+            entity_id: declared.id,
+            drift: drift(
+                &declared.objective,
+                by_entity
+                    .get(&declared.id)
+                    .map(Vec::as_slice)
+                    .unwrap_or(&[]),
+                &thresholds,
+            ),
+            name: declared.name,
+        })
+        .filter(|entry| {
+            query.all
+                || matches!(
+                    entry.drift.status,
+                    DriftStatus::Breaching | DriftStatus::OverPromising
+                )
+        })
+        .collect();
+
+    entities.sort_by(|a, b| {
+        b.drift
+            .status
+            .cmp(&a.drift.status)
+            .then(b.drift.worst_shortfall.total_cmp(&a.drift.worst_shortfall))
+    });
+
+    Ok(AppJson(DriftReport {
+        days: query.days,
+        entities,
+    }))
+}
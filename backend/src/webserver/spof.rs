@@ -0,0 +1,179 @@
+use axum::{
+    Router,
+    extract::{Query, State},
+    routing::get,
+};
+use serde::Serialize;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::{
+    MyState,
+    error::MyError,
+    graph::Graph,
+    webserver::{AppJson, PageOptions, entities::EntitySummary},
+};
+
+/// An entity whose failure is not absorbed by a redundant sibling
+#[derive(Serialize, Debug, ToSchema)]
+pub struct SinglePoint {
+    #[serde(flatten)]
+    pub entity: EntitySummary,
+    /// Entities that fail with it, following dependencies up to every consumer
+    pub impacted: usize,
+    /// Direct consumers with no other dependency of its type
+    pub sole_dependency_of: Vec<EntitySummary>,
+    /// Removing it splits the dependency graph in two
+    pub articulation_point: bool,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct SpofReport {
+    /// Single points of failure found, over all pages
+    pub total: usize,
+    pub single_points: Vec<SinglePoint>,
+    pub pagination: PageOptions,
+}
+
+#[derive(OpenApi)]
+#[openapi(paths(report))]
+pub(crate) struct SpofApi;
+
+pub fn spof_apis() -> Router<MyState> {
+    Router::new().route("/", get(report))
+}
+
+/// Rank the single points of failure so the most damaging are made redundant first
+fn single_points(graph: &Graph) -> Vec<SinglePoint> {
+    let sole = graph.sole_dependency_of();
+    let dependencies = graph.failure_dependencies();
+    let mut scratch = dependencies.scratch();
+    let mut articulation = vec![false; graph.len()];
+    for node in graph.articulation_points() {
+        articulation[node] = true;
+    }
+
+    let mut points: Vec<SinglePoint> = (0..graph.len())
+        .filter(|&node| !sole[node].is_empty() || articulation[node])
+        .map(|node| SinglePoint {
+            entity: EntitySummary::from(&graph.entities[node]),
+            impacted: dependencies.cascade_with(&[node], &mut scratch).len() - 1,
+            sole_dependency_of: sole[node]
+                .iter()
+                .map(|&consumer| EntitySummary::from(&graph.entities[consumer]))
+                .collect(),
+            articulation_point: articulation[node],
+        })
+        .collect();
+
+    points.sort_by(|a, b| {
+        b.impacted
+            .cmp(&a.impacted)
+            .then(b.sole_dependency_of.len().cmp(&a.sole_dependency_of.len()))
+            .then(b.articulation_point.cmp(&a.articulation_point))
+            .then(a.entity.id.cmp(&b.entity.id))
+    });
+    points
+}
+
+/// Single points of failure, most impactful first
+///
+/// Dependencies of the same type back each other up, of different types are all needed. An
+/// entity is listed when some consumer has no other dependency of its type, or when it is an
+/// articulation point of the dependency graph. `impacted` counts the entities that fail with
+/// it as failures cascade up through consumers.
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -v http://localhost:8080/spof\?size\=20
+/// ```
+#[utoipa::path(
+    get,
+    path = "/spof",
+    tag = "analysis",
+    params(PageOptions),
+    responses(
+        (status = 200, description = "A page of single points of failure, most impactful first", body = SpofReport),
+    )
+)]
+pub(crate) async fn report(
+    State(state): State<MyState>,
+    Query(options): Query<PageOptions>,
+) -> Result<AppJson<SpofReport>, MyError> {
+    let options = PageOptions::defaulting(options);
+    let size = options.size.unwrap().max(0) as usize;
+    let page = options.page.unwrap().max(0) as usize;
+
+    let graph = Graph::load(&state.db_state.pool()).await?;
+    // A cascade per candidate is CPU bound, keep it off the async workers
+    let points = tokio::task::spawn_blocking(move || single_points(&graph))
+        .await
+        .map_err(|_| MyError::Message("Single point of failure analysis failed"))?;
+
+    Ok(AppJson(SpofReport {
+        total: points.len(),
+        single_points: points
+            .into_iter()
+            .skip(page.saturating_mul(size))
+            .take(size)
+            .collect(),
+        pagination: options,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+    use crate::webserver::{entities::Entity, relationships::Relationship};
+
+    fn entity(id: i64, entity_type: &str) -> Entity {
+        serde_json::from_value(json!({
+            "id": id, "name": format!("e{id}"), "type": entity_type,
+            "p99_millis": 0, "p95_millis": 0, "availability": 100.0, "throughput_rps": 0,
+            "attributes": {},
+        }))
+        .unwrap()
+    }
+
+    fn relationship(from_id: i64, to_id: i64) -> Relationship {
+        serde_json::from_value(json!({
+            "id": from_id * 100 + to_id, "from_id": from_id, "to_id": to_id,
+            "relationship_type": "depends_on", "attributes": {},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn redundant_siblings_are_not_single_points() {
+        // web -> api -> two databases on one vm, api -> cache
+        let graph = Graph::new(
+            vec![
+                entity(1, "service"),
+                entity(2, "service"),
+                entity(3, "database"),
+                entity(4, "database"),
+                entity(5, "vm"),
+                entity(6, "cache"),
+            ],
+            vec![
+                relationship(1, 2),
+                relationship(2, 3),
+                relationship(2, 4),
+                relationship(3, 5),
+                relationship(4, 5),
+                relationship(2, 6),
+            ],
+        );
+
+        let points = single_points(&graph);
+        let ranked: Vec<(i64, usize, bool)> = points
+            .iter()
+            .map(|p| (p.entity.id, p.impacted, p.articulation_point))
+            .collect();
+        // the vm takes both databases, api and web down, the cache takes api and web, api web
+        assert_eq!(ranked, vec![(5, 4, false), (6, 2, false), (2, 1, true)]);
+        assert_eq!(points[0].sole_dependency_of.len(), 2);
+    }
+}
@@ -0,0 +1,224 @@
+//! The neighbourhood of an entity as nodes and edges
+//!
+//! Entities within a number of hops are found by a recursive CTE, following relationships down
+//! to dependencies, up to dependents or both ways, optionally only along some relationship
+//! types. A label selector narrows the entities returned, not the walk, and the edges are the
+//! relationships between the entities returned. A walk reaching more than `MAX_NODES`
+//! entities is refused rather than returning the whole topology unpaged.
+
+use axum::extract::{Path, Query, State};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::{
+    MyState,
+    error::MyError,
+    webserver::{
+        AppJson, DbBigSerial, ErrorResponse, entities::Entity, labels::SelectorQuery,
+        relationships::Relationship,
+    },
+};
+
+/// Hops allowed in one request, deeper views can be fetched from the edge of a previous one
+pub const MAX_DEPTH: i32 = 10;
+/// Entities one walk may reach, before the selector narrows them
+pub const MAX_NODES: i64 = 1000;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// Follow relationships from an entity to its dependencies
+    Down,
+    /// Follow relationships from an entity to its dependents
+    Up,
+    #[default]
+    Both,
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SubgraphQuery {
+    /// Hops from the entity, 1 to 10
+    #[serde(default = "default_depth")]
+    #[param(default = 2)]
+    pub depth: i32,
+    #[serde(default)]
+    #[param(inline)]
+    pub direction: Direction,
+    /// Comma separated relationship types to follow, all types when not set or empty
+    pub types: Option<String>,
+}
+
+fn default_depth() -> i32 {
+    2
+}
+
+impl SubgraphQuery {
+    fn relationship_types(&self) -> Option<Vec<String>> {
+        let types: Vec<String> = self
+            .types
+            .as_deref()?
+            .split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(String::from)
+            .collect();
+        (!types.is_empty()).then_some(types)
+    }
+}
+
+/// An entity with the number of hops to reach it
+#[derive(Serialize, Debug, sqlx::FromRow, ToSchema)]
+pub struct SubgraphNode {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub entity: Entity,
+    pub depth: i32,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Subgraph {
+    #[schema(value_type = i64)]
+    pub root: DbBigSerial,
+    pub depth: i32,
+    pub direction: Direction,
+    /// Nearest first, the root at depth 0 whether or not it matches the selector
+    pub nodes: Vec<SubgraphNode>,
+    pub edges: Vec<Relationship>,
+}
+
+#[derive(OpenApi)]
+#[openapi(paths(neighbourhood))]
+pub(crate) struct SubgraphApi;
+
+/// Entities within `depth` hops of the entity, with their depth, and the relationships between
+/// them
+///
+/// Entities not matching `selector` are walked through but left out, with their relationships.
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -v http://localhost:8080/entities/1/graph\?depth\=2\&direction\=down\&types\=depends_on,hosted_on
+/// curl -v http://localhost:8080/entities/1/graph\?selector\=tier%3Dcritical
+/// ```
+#[utoipa::path(
+    get,
+    path = "/entities/{id}/graph",
+    tag = "entities",
+    params(("id" = i64, Path, description = "Entity id"), SubgraphQuery, SelectorQuery),
+    responses(
+        (status = 200, description = "The neighbourhood of the entity", body = Subgraph),
+        (status = 404, description = "No entity with the id", body = ErrorResponse),
+        (status = 422, description = "Depth out of range, selector not valid or more than 1000 entities reached", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn neighbourhood(
+    Path(id): Path<DbBigSerial>,
+    State(state): State<MyState>,
+    Query(query): Query<SubgraphQuery>,
+    selector: SelectorQuery,
+) -> Result<AppJson<Subgraph>, MyError> {
+    if !(1..=MAX_DEPTH).contains(&query.depth) {
+        return Err(MyError::Validation(format!(
+            "depth must be between 1 and {MAX_DEPTH}"
+        )));
+    }
+    let types = query.relationship_types();
+    let pool = state.db_state.pool();
+
+    // Edges are walked from `here` to `there`, reversed relationships walk upwards. Distinct
+    // (entity, depth) rows bound the recursion on cycles.
+    let mut nodes = sqlx::query_as::<_, SubgraphNode>(
+        r#"
+        WITH RECURSIVE edges(here, there) AS (
+            SELECT from_id, to_id FROM relationships
+            WHERE $3 AND ($5::text[] IS NULL OR relationship_type = ANY($5))
+            UNION ALL
+            SELECT to_id, from_id FROM relationships
+            WHERE $4 AND ($5::text[] IS NULL OR relationship_type = ANY($5))
+        ),
+        reach(id, depth) AS (
+            SELECT id, 0 FROM entities WHERE id = $1
+            UNION
+            SELECT edges.there, reach.depth + 1
+            FROM reach JOIN edges ON edges.here = reach.id
+            WHERE reach.depth < $2
+        )
+        SELECT entities.*, hops.depth
+        FROM (SELECT id, MIN(depth) AS depth FROM reach GROUP BY id) hops
+        JOIN entities USING (id)
+        ORDER BY hops.depth, entities.id
+        LIMIT $6
+        "#,
+    )
+    .bind(id)
+    .bind(query.depth)
+    .bind(query.direction != Direction::Up)
+    .bind(query.direction != Direction::Down)
+    .bind(&types)
+    .bind(MAX_NODES + 1)
+    .fetch_all(&pool)
+    .await?;
+
+    if nodes.is_empty() {
+        return Err(MyError::SqlxError(sqlx::Error::RowNotFound));
+    }
+    if nodes.len() as i64 > MAX_NODES {
+        return Err(MyError::Validation(format!(
+            "more than {MAX_NODES} entities reached at depth {}, reduce depth or follow fewer types",
+            query.depth
+        )));
+    }
+    nodes.retain(|node| node.depth == 0 || selector.selector.matches(&node.entity.labels));
+
+    let ids: Vec<DbBigSerial> = nodes.iter().filter_map(|node| node.entity.id).collect();
+    let edges = sqlx::query_as::<_, Relationship>(
+        "SELECT * FROM relationships
+         WHERE from_id = ANY($1) AND to_id = ANY($1)
+           AND ($2::text[] IS NULL OR relationship_type = ANY($2))
+         ORDER BY id",
+    )
+    .bind(&ids)
+    .bind(&types)
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(AppJson(Subgraph {
+        root: id,
+        depth: query.depth,
+        direction: query.direction,
+        nodes,
+        edges,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::Uri;
+
+    use super::*;
+
+    fn parse(uri: &'static str) -> SubgraphQuery {
+        Query::try_from_uri(&Uri::from_static(uri)).unwrap().0
+    }
+
+    #[test]
+    fn query_defaults_and_types() {
+        let query = parse("/entities/1/graph?direction=up&types=depends_on,%20hosted_on,");
+        assert_eq!(query.depth, 2);
+        assert_eq!(query.direction, Direction::Up);
+        assert_eq!(
+            query.relationship_types(),
+            Some(vec!["depends_on".to_string(), "hosted_on".to_string()])
+        );
+
+        let query = parse("/entities/1/graph");
+        assert_eq!(query.direction, Direction::Both);
+        assert_eq!(query.relationship_types(), None);
+
+        // An empty list follows every type rather than none
+        let query = parse("/entities/1/graph?types=");
+        assert_eq!(query.relationship_types(), None);
+    }
+}
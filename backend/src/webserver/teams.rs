@@ -0,0 +1,323 @@
+use axum::http::StatusCode;
+use axum::{
+    Router,
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use utoipa::ToSchema;
+
+use crate::{
+    MyState,
+    error::MyError,
+    webserver::{AppJson, DbBigSerial, ListPages, PageOptions},
+};
+
+fn default_role() -> String {
+    "member".into()
+}
+
+/// A user belonging to a team
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+pub struct TeamMember {
+    #[schema(value_type = i64)]
+    pub user_id: DbBigSerial,
+    /// Free-form role within the team, e.g. `lead` or `member`
+    #[serde(default = "default_role")]
+    pub role: String,
+}
+
+/// One step of a team's escalation chain
+///
+/// Contacts are paged in ascending `level`, contacts sharing a level are paged together.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+pub struct EscalationContact {
+    pub level: i32,
+    /// Team member behind the contact, unset for shared rotations or mailing lists
+    #[serde(default)]
+    #[schema(value_type = Option<i64>)]
+    pub user_id: Option<DbBigSerial>,
+    /// How to reach the contact, e.g. `pager`, `phone`, `email`, `slack`
+    pub channel: String,
+    pub address: String,
+}
+
+/// A team that owns entities, with its members and escalation contacts
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct Team {
+    #[serde(default)]
+    #[schema(value_type = Option<i64>)]
+    pub id: Option<DbBigSerial>,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    #[sqlx(json)]
+    pub members: Vec<TeamMember>,
+    #[serde(default)]
+    #[sqlx(json)]
+    pub escalation: Vec<EscalationContact>,
+}
+
+impl Team {
+    /// Check the escalation chain is well formed before it is stored
+    pub fn validate(&self) -> Result<(), MyError> {
+        for contact in &self.escalation {
+            if contact.level < 1 {
+                return Err(MyError::Validation(format!(
+                    "escalation level must be at least 1 but was {}",
+                    contact.level
+                )));
+            }
+            if contact.channel.trim().is_empty() || contact.address.trim().is_empty() {
+                return Err(MyError::Validation(
+                    "escalation contacts need a channel and an address".into(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+const SELECT_TEAMS: &str = r#"
+    SELECT t.id, t.name, t.description,
+        COALESCE((
+            SELECT json_agg(json_build_object('user_id', m.user_id, 'role', m.role)
+                ORDER BY m.user_id)
+            FROM team_members m
+            WHERE m.team_id = t.id
+        ), '[]') AS members,
+        COALESCE((
+            SELECT json_agg(json_build_object('level', c.level, 'user_id', c.user_id,
+                    'channel', c.channel, 'address', c.address)
+                ORDER BY c.level, c.position)
+            FROM escalation_contacts c
+            WHERE c.team_id = t.id
+        ), '[]') AS escalation
+    FROM teams t
+    "#;
+
+pub fn team_apis() -> Router<MyState> {
+    Router::new()
+        .route("/", post(create).get(list))
+        .route("/{id}", get(read).put(update).delete(delete))
+}
+
+pub async fn fetch(pool: &PgPool, id: DbBigSerial) -> Result<Option<Team>, MyError> {
+    let team = sqlx::query_as::<_, Team>(&format!("{SELECT_TEAMS} WHERE t.id = $1"))
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(team)
+}
+
+async fn replace_members(
+    tx: &mut Transaction<'_, Postgres>,
+    id: DbBigSerial,
+    team: &Team,
+) -> Result<(), MyError> {
+    sqlx::query("DELETE FROM team_members WHERE team_id = $1")
+        .bind(id)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query("DELETE FROM escalation_contacts WHERE team_id = $1")
+        .bind(id)
+        .execute(&mut **tx)
+        .await?;
+
+    let user_ids: Vec<DbBigSerial> = team.members.iter().map(|m| m.user_id).collect();
+    let roles: Vec<&str> = team.members.iter().map(|m| m.role.as_str()).collect();
+
+    sqlx::query(
+        r#"
+        INSERT INTO team_members (team_id, user_id, role)
+        SELECT $1, user_id, role FROM UNNEST($2::BIGINT[], $3::VARCHAR[]) AS members(user_id, role)
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(id)
+    .bind(user_ids)
+    .bind(roles)
+    .execute(&mut **tx)
+    .await?;
+
+    let levels: Vec<i32> = team.escalation.iter().map(|c| c.level).collect();
+    let contact_users: Vec<Option<DbBigSerial>> =
+        team.escalation.iter().map(|c| c.user_id).collect();
+    let channels: Vec<&str> = team.escalation.iter().map(|c| c.channel.as_str()).collect();
+    let addresses: Vec<&str> = team.escalation.iter().map(|c| c.address.as_str()).collect();
+
+    sqlx::query(
+        r#"
+        INSERT INTO escalation_contacts (team_id, level, position, user_id, channel, address)
+        SELECT $1, level, position, user_id, channel, address
+        FROM UNNEST($2::INTEGER[], $3::BIGINT[], $4::VARCHAR[], $5::VARCHAR[])
+            WITH ORDINALITY AS contacts(level, user_id, channel, address, position)
+        "#,
+    )
+    .bind(id)
+    .bind(levels)
+    .bind(contact_users)
+    .bind(channels)
+    .bind(addresses)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// List team ids
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -v http://localhost:8080/teams\?page\=0\&size\=10
+/// ```
+async fn list(
+    State(state): State<MyState>,
+    Query(options): Query<PageOptions>,
+) -> Result<AppJson<ListPages>, MyError> {
+    let options = PageOptions::defaulting(options);
+
+    let ids =
+        sqlx::query_scalar::<_, DbBigSerial>("SELECT id FROM teams ORDER BY id LIMIT $1 OFFSET $2")
+            .bind(options.size)
+            .bind(options.page.unwrap() * options.size.unwrap())
+            .fetch_all(&state.db_state.pool())
+            .await?;
+
+    Ok(AppJson(ListPages {
+        pagination: options,
+        ids,
+    }))
+}
+
+/// Create a team with its members and escalation chain
+///
+/// # Example cURL Command
+///
+/// ```sh
+/// curl -X POST http://localhost:8080/teams \
+///      -H "Content-Type: application/json" \
+///      -d '{"name": "payments", "members": [{"user_id": 1, "role": "lead"}], "escalation": [{"level": 1, "user_id": 1, "channel": "pager", "address": "+441234567890"}, {"level": 2, "channel": "email", "address": "payments@example.com"}]}'
+/// ```
+async fn create(
+    State(state): State<MyState>,
+    AppJson(payload): AppJson<Team>,
+) -> Result<impl IntoResponse, MyError> {
+    if payload.id.is_some() {
+        return Err(MyError::Validation("ID must not be set".into()));
+    }
+    payload.validate()?;
+
+    let mut tx = state.db_state.pool().begin().await?;
+
+    let id = sqlx::query_scalar::<_, DbBigSerial>(
+        "INSERT INTO teams (name, description) VALUES ($1, $2) RETURNING id",
+    )
+    .bind(&payload.name)
+    .bind(&payload.description)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    replace_members(&mut tx, id, &payload).await?;
+
+    tx.commit().await?;
+
+    let team = fetch(&state.db_state.pool(), id)
+        .await?
+        .ok_or(MyError::Message("team missing after insert"))?;
+
+    Ok((StatusCode::CREATED, AppJson(team)).into_response())
+}
+
+async fn read(
+    Path(id): Path<DbBigSerial>,
+    State(state): State<MyState>,
+) -> Result<AppJson<Team>, MyError> {
+    let team = fetch(&state.db_state.pool(), id)
+        .await?
+        .ok_or(MyError::SqlxError(sqlx::Error::RowNotFound))?;
+
+    Ok(AppJson(team))
+}
+
+/// Update a team, replacing its members and escalation chain
+async fn update(
+    State(state): State<MyState>,
+    Path(id): Path<DbBigSerial>,
+    AppJson(payload): AppJson<Team>,
+) -> Result<impl IntoResponse, MyError> {
+    if payload.id.is_none() || id != payload.id.unwrap() {
+        return Err(MyError::Validation(
+            "ids on path and body must match for update".into(),
+        ));
+    }
+    payload.validate()?;
+
+    let mut tx = state.db_state.pool().begin().await?;
+
+    let updated = sqlx::query("UPDATE teams SET name = $2, description = $3 WHERE id = $1")
+        .bind(id)
+        .bind(&payload.name)
+        .bind(&payload.description)
+        .execute(&mut *tx)
+        .await?;
+
+    if updated.rows_affected() == 0 {
+        return Err(MyError::SqlxError(sqlx::Error::RowNotFound));
+    }
+
+    replace_members(&mut tx, id, &payload).await?;
+
+    tx.commit().await?;
+
+    let team = fetch(&state.db_state.pool(), id)
+        .await?
+        .ok_or(MyError::SqlxError(sqlx::Error::RowNotFound))?;
+
+    Ok(AppJson(team))
+}
+
+/// Delete a team, the entities it owned are left without an owner
+async fn delete(
+    State(state): State<MyState>,
+    Path(id): Path<DbBigSerial>,
+) -> Result<AppJson<Team>, MyError> {
+    let team = fetch(&state.db_state.pool(), id)
+        .await?
+        .ok_or(MyError::SqlxError(sqlx::Error::RowNotFound))?;
+
+    sqlx::query("DELETE FROM teams WHERE id = $1")
+        .bind(id)
+        .execute(&state.db_state.pool())
+        .await?;
+
+    Ok(AppJson(team))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn escalation_levels_start_at_one() {
+        let mut team: Team = serde_json::from_str(
+            r#"{"name": "payments", "members": [{"user_id": 1}],
+                "escalation": [{"level": 1, "channel": "pager", "address": "+441234567890"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(team.members[0].role, "member");
+        assert!(team.validate().is_ok());
+
+        team.escalation[0].level = 0;
+        assert!(matches!(team.validate(), Err(MyError::Validation(_))));
+
+        team.escalation[0].level = 2;
+        team.escalation[0].address = " ".into();
+        assert!(matches!(team.validate(), Err(MyError::Validation(_))));
+    }
+}
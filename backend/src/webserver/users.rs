@@ -7,16 +7,18 @@ use axum::{
 };
 use serde::{Deserialize, Serialize};
 use tracing::info;
+use utoipa::{OpenApi, ToSchema};
 
 use crate::{
     MyState,
     error::MyError,
-    webserver::{AppJson, DbBigSerial, ListPages, PageOptions},
+    webserver::{AppJson, DbBigSerial, ErrorResponse, ListPages, PageOptions},
 };
 
-#[derive(Deserialize, Serialize, Debug, sqlx::FromRow, PartialEq, Clone)]
+#[derive(Deserialize, Serialize, Debug, sqlx::FromRow, PartialEq, Clone, ToSchema)]
 // #[derive(ParquetRecordWriter)]
 pub struct User {
+    #[schema(value_type = Option<i64>)]
     pub id: Option<DbBigSerial>,
     pub forename: String,
     pub surname: String,
@@ -41,6 +43,10 @@ pub(crate) fn user_apis() -> Router<MyState> {
     // Add other user-related routes here
 }
 
+#[derive(OpenApi)]
+#[openapi(paths(create, list, read, update, delete))]
+pub(crate) struct UserApi;
+
 /// Creates a new user in the database.
 ///
 /// # Arguments
@@ -65,6 +71,16 @@ pub(crate) fn user_apis() -> Router<MyState> {
 ///      -H "Content-Type: application/json" \
 ///      -d '{"forename": "John", "surname": "Doe", "password": "secret"}'
 /// ```
+#[utoipa::path(
+    post,
+    path = "/users",
+    tag = "users",
+    request_body = User,
+    responses(
+        (status = 201, description = "User created", body = User),
+        (status = 500, description = "Id set on the new user", body = ErrorResponse),
+    )
+)]
 pub async fn create(
     State(state): State<MyState>,
     AppJson(user): AppJson<User>,
@@ -88,7 +104,7 @@ pub async fn create(
     .bind(&user.forename)
     .bind(&user.surname)
     .bind(&user.password)
-    .fetch_one(&state.db_state.pool_pg)
+    .fetch_one(&state.db_state.pool())
     .await
     .map_err(MyError::from)?;
 
@@ -100,6 +116,13 @@ pub async fn create(
 /// ```sh
 /// curl -v http://localhost:8080/users\?page\=3
 /// ```
+#[utoipa::path(
+    get,
+    path = "/users",
+    tag = "users",
+    params(PageOptions),
+    responses((status = 200, description = "A page of user ids", body = ListPages))
+)]
 pub async fn list(
     State(state): State<MyState>,
     Query(options): Query<PageOptions>,
@@ -115,7 +138,7 @@ pub async fn list(
     )
     .bind(options.size)
     .bind(options.page.unwrap() * options.size.unwrap())
-    .fetch_all(&state.db_state.pool_pg)
+    .fetch_all(&state.db_state.pool())
     .await
     .map_err(MyError::from)?;
 
@@ -132,6 +155,16 @@ pub async fn list(
 /// ```sh
 /// curl -v http://localhost:8080/users/3
 /// ```
+#[utoipa::path(
+    get,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = i64, Path, description = "User id")),
+    responses(
+        (status = 200, description = "The user", body = User),
+        (status = 404, description = "No user with the id", body = ErrorResponse),
+    )
+)]
 pub async fn read(
     Path(id): Path<DbBigSerial>,
     State(state): State<MyState>,
@@ -143,7 +176,7 @@ pub async fn read(
         "#,
     )
     .bind(id)
-    .fetch_one(&state.db_state.pool_pg)
+    .fetch_one(&state.db_state.pool())
     .await
     .map_err(MyError::from)?;
 
@@ -153,14 +186,26 @@ pub async fn read(
 /// Update a user
 ///
 /// This function will update a user in the database
+#[utoipa::path(
+    put,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = i64, Path, description = "User id, matching the id in the body")),
+    request_body = User,
+    responses(
+        (status = 200, description = "The updated user", body = User),
+        (status = 404, description = "No user with the id", body = ErrorResponse),
+        (status = 422, description = "Path and body ids differ", body = ErrorResponse),
+    )
+)]
 pub async fn update(
     State(state): State<MyState>,
     Path(id): Path<DbBigSerial>,
     AppJson(user): AppJson<User>,
 ) -> Result<impl IntoResponse, MyError> {
     if user.id.is_none() || id != user.id.unwrap() {
-        return Err(MyError::Message(
-            "ids on path and body must match for update",
+        return Err(MyError::Validation(
+            "ids on path and body must match for update".into(),
         ));
     }
 
@@ -176,7 +221,7 @@ pub async fn update(
     .bind(&user.forename)
     .bind(&user.surname)
     .bind(&user.password)
-    .fetch_one(&state.db_state.pool_pg)
+    .fetch_one(&state.db_state.pool())
     .await
     .map_err(MyError::from)?;
 
@@ -186,6 +231,16 @@ pub async fn update(
 /// Delete a user
 ///
 /// This function will delete a user from the database
+#[utoipa::path(
+    delete,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = i64, Path, description = "User id")),
+    responses(
+        (status = 200, description = "The deleted user", body = User),
+        (status = 404, description = "No user with the id", body = ErrorResponse),
+    )
+)]
 pub async fn delete(
     Path(id): Path<DbBigSerial>,
     State(state): State<MyState>,
@@ -198,7 +253,7 @@ pub async fn delete(
         "#,
     )
     .bind(id)
-    .fetch_one(&state.db_state.pool_pg)
+    .fetch_one(&state.db_state.pool())
     .await
     .map_err(MyError::from)?;
 